@@ -2,15 +2,26 @@ use failure::Error;
 use futures::IntoFuture;
 
 use aws_lambda_gateway::NewApiGatewayProxy;
-use aws_lambda_runtime::Handler;
+#[cfg(feature = "std-future")]
+use aws_lambda_runtime::compat;
+use aws_lambda_runtime::{Context, ContextHandler, Handler};
 
-pub use aws_lambda_gateway::{http, Body};
+pub use aws_lambda_gateway::{
+    http, Authorizer, BinaryMediaTypes, Body, Elb, Identity, PathParameters, RequestContext,
+    RequestExt, StageVariables,
+};
 
 pub type Request = http::Request<Body>;
 pub type Response = http::Response<Body>;
 
 /// Start the lambda gateway runtime using the provided handler function.
 ///
+/// The handler serves the events of API Gateway REST and HTTP APIs,
+/// Application Load Balancers and Function URLs alike. The source of every
+/// event is detected from its payload, and the response is sent back in the
+/// format that the source expects. Invocations with any other kind of event
+/// fail with an error that describes the event that was received.
+///
 /// The function will block until the runtime shuts down or returns
 /// with an error.
 ///
@@ -44,6 +55,158 @@ where
         .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
 }
 
+/// Start the lambda gateway runtime using the provided async handler function.
+///
+/// This is similar to `start`, but accepts handler functions that return a
+/// `std::future::Future`, such as `async` blocks.
+///
+/// This function is only available when the `std-future` feature is enabled.
+///
+/// ## Example
+///
+/// ```edition2018,no_run
+/// use aws_lambda as lambda;
+///
+/// fn main() {
+///     lambda::gateway::start_async(|_req| async {
+///         let res = lambda::gateway::response()
+///             .status(200)
+///             .body("Hello ƛ!".into())?;
+///         Ok(res)
+///     })
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// This function will panic if it fails to create the runtime.
+#[cfg(feature = "std-future")]
+pub fn start_async<F, Fut>(f: F)
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: ::std::future::Future<Output = Result<Response, Error>> + Send + 'static,
+{
+    start(move |req| compat::into_01(f(req)))
+}
+
+/// Start the lambda gateway runtime using the provided handler function,
+/// which receives the invocation `Context` as an explicit argument.
+///
+/// The function will block until the runtime shuts down or returns
+/// with an error.
+///
+/// ## Example
+///
+/// ```no_run
+/// extern crate aws_lambda as lambda;
+///
+/// fn main() {
+///     lambda::gateway::start_with_context(|_req, ctx: lambda::Context| {
+///         let res = lambda::gateway::response()
+///             .status(200)
+///             .body(format!("Hello from {}!", ctx.aws_request_id()).into())?;
+///         Ok(res)
+///     })
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// This function will panic if it fails to create the runtime.
+pub fn start_with_context<F, S>(f: F)
+where
+    F: Fn(Request, Context) -> S + Send + Sync + 'static,
+    S: IntoFuture<Item = Response, Error = Error>,
+    S::Future: Send + 'static,
+{
+    let service = NewApiGatewayProxy::new(ContextHandler::from(f));
+    ::Runtime::new()
+        .and_then(|runtime| runtime.start_service(service))
+        .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
+}
+
+/// Start the lambda gateway runtime using the provided handler function,
+/// sending the responses that match the given binary media types
+/// base64-encoded.
+///
+/// Other responses are sent as text, unless their body isn't valid UTF-8,
+/// even if the body was created from bytes, which `start` would send
+/// base64-encoded. See `BinaryMediaTypes` for details.
+///
+/// ## Example
+///
+/// ```no_run
+/// extern crate aws_lambda as lambda;
+///
+/// use lambda::gateway::BinaryMediaTypes;
+///
+/// fn main() {
+///     let media_types = BinaryMediaTypes::new().insert("image/*");
+///     lambda::gateway::start_with_binary_media_types(media_types, |_req| {
+///         let res = lambda::gateway::response()
+///             .status(200)
+///             .header("Content-Type", "image/svg+xml")
+///             .body("<svg xmlns=\"http://www.w3.org/2000/svg\"/>".into())?;
+///         Ok(res)
+///     })
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// This function will panic if it fails to create the runtime.
+pub fn start_with_binary_media_types<F, S>(media_types: BinaryMediaTypes, f: F)
+where
+    F: Fn(Request) -> S + Send + Sync + 'static,
+    S: IntoFuture<Item = Response, Error = Error>,
+    S::Future: Send + 'static,
+{
+    let service = NewApiGatewayProxy::new(Handler::from(f)).binary_media_types(media_types);
+    ::Runtime::new()
+        .and_then(|runtime| runtime.start_service(service))
+        .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
+}
+
 pub fn response() -> http::response::Builder {
     http::response::Builder::new()
 }
+
+/// Support for API Gateway WebSocket APIs.
+///
+/// See `aws_lambda_gateway::websocket` for details.
+pub mod websocket {
+    pub use aws_lambda_gateway::websocket::*;
+
+    /// Start the lambda runtime with the given router of WebSocket events.
+    ///
+    /// The function will block until the runtime shuts down or returns
+    /// with an error.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// extern crate aws_lambda as lambda;
+    ///
+    /// use lambda::gateway::websocket::{self, Router};
+    ///
+    /// fn main() {
+    ///     let router = Router::new()
+    ///         .route("$connect", |_event| {
+    ///             lambda::gateway::response().status(200).body("".into())
+    ///         })
+    ///         .route("$default", |event| {
+    ///             lambda::gateway::response().status(200).body(event.body)
+    ///         });
+    ///     websocket::start(router)
+    /// }
+    /// ```
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if it fails to create the runtime.
+    pub fn start(router: Router) {
+        ::Runtime::new()
+            .and_then(|runtime| runtime.start_service(router))
+            .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
+    }
+}
@@ -12,11 +12,13 @@ extern crate aws_lambda_events;
 extern crate aws_lambda_gateway;
 extern crate aws_lambda_runtime;
 
-pub use aws_lambda_runtime::{Context, Runtime};
+pub use aws_lambda_runtime::{Context, IntoInvokeError, Runtime, RuntimeBuilder, Server};
 
 pub use aws_lambda_events::event;
 pub use aws_lambda_runtime::context;
+pub use aws_lambda_runtime::current_thread;
 pub use aws_lambda_runtime::env;
+pub use aws_lambda_runtime::memory;
 
 #[cfg(feature = "gateway")]
 pub mod gateway;
@@ -43,6 +45,13 @@ pub mod logger;
 ///
 /// If you wish to handle this case more, you can use `Runtime::start`
 /// instead.
+///
+/// ## Errors
+///
+/// The handler function is expected to return errors as `failure::Error`,
+/// which allows the use of `?` with most error types. To return errors of
+/// other types, you can use `Runtime::start`, which accepts any error type
+/// that implements `IntoInvokeError`.
 pub fn start<F, R, S>(f: F)
 where
     F: Fn(R) -> S + Send + Sync + 'static,
@@ -55,3 +64,140 @@ where
         .and_then(|runtime| runtime.start(f))
         .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
 }
+
+/// Start the lambda runtime using the provided async handler function.
+///
+/// This is similar to `start`, but accepts handler functions that return a
+/// `std::future::Future`, such as `async` blocks. The current `Context` remains
+/// available across `.await` points.
+///
+/// This function is only available when the `std-future` feature is enabled.
+///
+/// The function will block until the runtime shuts down or returns
+/// with an error.
+///
+/// ## Example
+///
+/// ```edition2018,no_run
+/// use aws_lambda as lambda;
+///
+/// fn main() {
+///     lambda::start_async(|()| async {
+///         let ctx = lambda::Context::current();
+///         Ok(format!("Hello from {}!", ctx.invoked_function_arn()))
+///     })
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// This function will panic if it fails to create the runtime.
+///
+/// If you wish to handle this case more, you can use `Runtime::start_async`
+/// instead.
+#[cfg(feature = "std-future")]
+pub fn start_async<F, R, Fut, T>(f: F)
+where
+    F: Fn(R) -> Fut + Send + Sync + 'static,
+    Fut: ::std::future::Future<Output = Result<T, Error>> + Send + 'static,
+    T: Serialize + Send + 'static,
+    R: DeserializeOwned + Send + 'static,
+{
+    Runtime::new()
+        .and_then(|runtime| runtime.start_async(f))
+        .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
+}
+
+/// Start the lambda runtime using the provided handler function, which
+/// receives the invocation `Context` as an explicit argument.
+///
+/// Unlike `Context::current()`, this makes the context a regular dependency
+/// of the handler function, which can be provided directly in unit tests.
+///
+/// The function will block until the runtime shuts down or returns
+/// with an error.
+///
+/// ## Example
+///
+/// ```no_run
+/// extern crate aws_lambda as lambda;
+///
+/// fn main() {
+///     lambda::start_with_context(|(), ctx: lambda::Context| {
+///         Ok(format!("Hello from {}!", ctx.invoked_function_arn()))
+///     })
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// This function will panic if it fails to create the runtime.
+///
+/// If you wish to handle this case more, you can use `Runtime::start_with_context`
+/// instead.
+pub fn start_with_context<F, R, S>(f: F)
+where
+    F: Fn(R, Context) -> S + Send + Sync + 'static,
+    S: IntoFuture<Error = Error> + Send,
+    S::Future: Send,
+    S::Item: Serialize + Send + 'static,
+    R: DeserializeOwned + Send + 'static,
+{
+    Runtime::new()
+        .and_then(|runtime| runtime.start_with_context(f))
+        .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
+}
+
+/// Start the lambda runtime using the provided initialization future and handler function.
+///
+/// The initialization future is run once, before the first invocation is
+/// accepted. It can be used to set up resources that are expensive to create,
+/// such as HTTP clients or database connection pools. The resulting state is
+/// passed by reference into the handler function on every invocation, together
+/// with the invocation `Context`.
+///
+/// The function will block until the runtime shuts down or returns
+/// with an error.
+///
+/// ## Example
+///
+/// ```no_run
+/// extern crate aws_lambda as lambda;
+///
+/// struct Config {
+///     greeting: String,
+/// }
+///
+/// fn main() {
+///     let init = Ok(Config {
+///         greeting: "Hello".to_owned(),
+///     });
+///
+///     lambda::start_with_init(init, |config: &Config, (), ctx: lambda::Context| {
+///         Ok(format!("{} from {}!", config.greeting, ctx.invoked_function_arn()))
+///     })
+/// }
+/// ```
+///
+/// ## Panics
+///
+/// This function will panic if it fails to create the runtime, or if the
+/// initialization future resolves with an error.
+///
+/// If you wish to handle these cases more, you can use `Runtime::start_with_init`
+/// instead.
+pub fn start_with_init<I, T, F, R, S>(init: I, f: F)
+where
+    I: IntoFuture<Item = T, Error = Error>,
+    I::Future: Send + 'static,
+    T: Send + Sync + 'static,
+    F: Fn(&T, R, Context) -> S + Send + Sync + 'static,
+    S: IntoFuture<Error = Error> + Send,
+    S::Future: Send,
+    S::Item: Serialize + Send + 'static,
+    R: DeserializeOwned + Send + 'static,
+{
+    Runtime::new()
+        .and_then(|runtime| runtime.start_with_init(init, f))
+        .unwrap_or_else(|err| panic!("failed to start runtime: {}", err))
+}
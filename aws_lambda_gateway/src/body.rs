@@ -1,12 +1,20 @@
+use std::str;
+
+use bytes::{Buf, Bytes};
 use serde::Deserialize;
 
 #[derive(Debug, Clone)]
 pub(crate) enum Inner {
     Empty,
-    Utf8(String),
-    Binary(Vec<u8>),
+    /// Bytes that were created from text.
+    Utf8(Bytes),
+    Binary(Bytes),
 }
 
+/// Body of a request or response.
+///
+/// The body is backed by a reference-counted `Bytes` buffer,
+/// so cloning it or converting it into `Bytes` does not copy the data.
 #[derive(Debug, Clone)]
 pub struct Body(pub(crate) Inner);
 
@@ -14,16 +22,22 @@ impl Body {
     pub fn as_bytes(&self) -> &[u8] {
         match self.0 {
             Inner::Empty => &[],
-            Inner::Utf8(ref text) => text.as_bytes(),
-            Inner::Binary(ref bytes) => bytes.as_slice(),
+            Inner::Utf8(ref bytes) | Inner::Binary(ref bytes) => bytes,
         }
     }
 
-    pub fn as_str(&self) -> Result<&str, ::std::str::Utf8Error> {
+    pub fn as_str(&self) -> Result<&str, str::Utf8Error> {
         match self.0 {
             Inner::Empty => Ok(""),
-            Inner::Utf8(ref text) => Ok(text.as_str()),
-            Inner::Binary(ref bytes) => ::std::str::from_utf8(bytes),
+            Inner::Utf8(ref bytes) | Inner::Binary(ref bytes) => str::from_utf8(bytes),
+        }
+    }
+
+    /// Convert the body into a shared `Bytes` buffer, without copying.
+    pub fn into_bytes(self) -> Bytes {
+        match self.0 {
+            Inner::Empty => Bytes::new(),
+            Inner::Utf8(bytes) | Inner::Binary(bytes) => bytes,
         }
     }
 
@@ -41,6 +55,38 @@ impl AsRef<[u8]> for Body {
     }
 }
 
+/// Reads the body from the front, so it can be handed to consumers
+/// of `Buf` without copying it into another buffer first.
+impl Buf for Body {
+    fn remaining(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        let (mut bytes, is_text) = match ::std::mem::replace(&mut self.0, Inner::Empty) {
+            Inner::Empty => (Bytes::new(), false),
+            Inner::Utf8(bytes) => (bytes, true),
+            Inner::Binary(bytes) => (bytes, false),
+        };
+        bytes.advance(cnt);
+        // the remaining bytes are only text if they don't start
+        // in the middle of a character
+        let is_char_boundary = match bytes.first() {
+            Some(&b) => b & 0xc0 != 0x80,
+            None => true,
+        };
+        self.0 = if is_text && is_char_boundary {
+            Inner::Utf8(bytes)
+        } else {
+            Inner::Binary(bytes)
+        };
+    }
+}
+
 impl Default for Body {
     fn default() -> Body {
         Body(Inner::Empty)
@@ -49,24 +95,69 @@ impl Default for Body {
 
 impl From<String> for Body {
     fn from(text: String) -> Body {
-        Body(Inner::Utf8(text))
+        Body(Inner::Utf8(Bytes::from(text)))
     }
 }
 
 impl<'a> From<&'a str> for Body {
     fn from(text: &'a str) -> Body {
-        Body(Inner::Utf8(text.to_owned()))
+        Body(Inner::Utf8(Bytes::from(text)))
     }
 }
 
 impl From<Vec<u8>> for Body {
     fn from(bytes: Vec<u8>) -> Body {
-        Body(Inner::Binary(bytes))
+        Body(Inner::Binary(Bytes::from(bytes)))
     }
 }
 
 impl<'a> From<&'a [u8]> for Body {
     fn from(bytes: &'a [u8]) -> Body {
-        Body(Inner::Binary(bytes.to_vec()))
+        Body(Inner::Binary(Bytes::from(bytes)))
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Body {
+        Body(Inner::Binary(bytes))
+    }
+}
+
+impl From<Body> for Bytes {
+    fn from(body: Body) -> Bytes {
+        body.into_bytes()
+    }
+}
+
+#[test]
+fn body_converts_from_and_into_bytes_without_copying() {
+    let bytes = Bytes::from(vec![0; 64]);
+    let body = Body::from(bytes.clone());
+    assert_eq!(body.as_bytes().as_ptr(), bytes.as_ptr());
+    assert_eq!(Bytes::from(body).as_ptr(), bytes.as_ptr());
+}
+
+#[test]
+fn body_buf_advances_without_copying() {
+    let bytes = Bytes::from("héllo wörld ".repeat(8));
+    let mut body = Body::from(bytes.clone());
+    assert_eq!(body.as_bytes().as_ptr(), bytes.as_ptr());
+
+    body.advance(7);
+    assert_eq!(body.remaining(), bytes.len() - 7);
+    assert_eq!(body.bytes(), &bytes[7..]);
+    assert_eq!(body.clone().into_bytes().as_ptr(), bytes[7..].as_ptr());
+
+    let mut text = Body::from("héllo");
+    text.advance(2);
+    assert!(text.as_str().is_err());
+    assert_eq!(text.bytes(), &"héllo".as_bytes()[2..]);
+
+    let mut text = Body::from("héllo");
+    text.advance(3);
+    match text.0 {
+        Inner::Utf8(_) => {}
+        ref inner => panic!("expected text, got {:?}", inner),
     }
+    assert_eq!(text.as_str().unwrap(), "llo");
 }
@@ -0,0 +1,157 @@
+use std::collections::hash_map::{self, HashMap};
+
+use http;
+use serde_json::Value;
+
+use body::Body;
+
+/// Information about the API Gateway request, such as the stage,
+/// the request id and the identity of the caller.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RequestContext {
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    #[serde(rename = "apiId")]
+    pub api_id: Option<String>,
+    #[serde(rename = "domainName")]
+    pub domain_name: Option<String>,
+    #[serde(rename = "resourceId")]
+    pub resource_id: Option<String>,
+    #[serde(rename = "resourcePath")]
+    pub resource_path: Option<String>,
+    #[serde(rename = "httpMethod")]
+    pub http_method: Option<String>,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    /// Route that matched the request, for HTTP APIs.
+    #[serde(rename = "routeKey")]
+    pub route_key: Option<String>,
+    pub stage: Option<String>,
+    pub protocol: Option<String>,
+    pub identity: Identity,
+    pub authorizer: Option<Authorizer>,
+    /// Load balancer that forwarded the request, for Application Load Balancer targets.
+    pub elb: Option<Elb>,
+}
+
+/// Information about the Application Load Balancer that forwarded a request.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Elb {
+    #[serde(rename = "targetGroupArn")]
+    pub target_group_arn: Option<String>,
+}
+
+/// Identity of the caller of an API Gateway request.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Identity {
+    #[serde(rename = "sourceIp")]
+    pub source_ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    #[serde(rename = "apiKey")]
+    pub api_key: Option<String>,
+    #[serde(rename = "accessKey")]
+    pub access_key: Option<String>,
+    pub caller: Option<String>,
+    pub user: Option<String>,
+    #[serde(rename = "userArn")]
+    pub user_arn: Option<String>,
+    #[serde(rename = "principalOrgId")]
+    pub principal_org_id: Option<String>,
+    #[serde(rename = "cognitoIdentityId")]
+    pub cognito_identity_id: Option<String>,
+    #[serde(rename = "cognitoIdentityPoolId")]
+    pub cognito_identity_pool_id: Option<String>,
+    #[serde(rename = "cognitoAuthenticationType")]
+    pub cognito_authentication_type: Option<String>,
+    #[serde(rename = "cognitoAuthenticationProvider")]
+    pub cognito_authentication_provider: Option<String>,
+}
+
+/// Output of the authorizer that was invoked for an API Gateway request.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Authorizer {
+    /// Principal returned by a custom authorizer.
+    #[serde(rename = "principalId")]
+    pub principal_id: Option<String>,
+    /// Claims of the token validated by a Cognito user pool authorizer.
+    pub claims: HashMap<String, Value>,
+    /// Scopes of the token validated by a JWT or Cognito user pool authorizer.
+    pub scopes: Option<Vec<String>>,
+    /// Any other context returned by the authorizer.
+    #[serde(flatten)]
+    pub context: HashMap<String, Value>,
+}
+
+/// Values captured by the path parameters of the resource, such as `{proxy+}`.
+#[derive(Clone, Debug, Default)]
+pub struct PathParameters(pub(crate) HashMap<String, String>);
+
+impl PathParameters {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|value| value.as_str())
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<String, String> {
+        self.0.iter()
+    }
+}
+
+/// Stage variables of the API Gateway stage that received the request.
+#[derive(Clone, Debug, Default)]
+pub struct StageVariables(pub(crate) HashMap<String, String>);
+
+impl StageVariables {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|value| value.as_str())
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<String, String> {
+        self.0.iter()
+    }
+}
+
+/// Resource path template that matched the request, such as `/{proxy+}`.
+#[derive(Clone, Debug)]
+pub(crate) struct Resource(pub(crate) String);
+
+/// Extension methods to access the API Gateway specific parts of a request.
+pub trait RequestExt {
+    /// Information about the request, if it was provided by API Gateway.
+    fn request_context(&self) -> Option<&RequestContext>;
+
+    /// Values captured by the path parameters of the resource.
+    fn path_parameters(&self) -> Option<&PathParameters>;
+
+    /// Stage variables of the stage that received the request.
+    fn stage_variables(&self) -> Option<&StageVariables>;
+
+    /// Resource path template that matched the request.
+    fn resource(&self) -> Option<&str>;
+}
+
+impl RequestExt for http::Request<Body> {
+    fn request_context(&self) -> Option<&RequestContext> {
+        self.extensions().get()
+    }
+
+    fn path_parameters(&self) -> Option<&PathParameters> {
+        self.extensions().get()
+    }
+
+    fn stage_variables(&self) -> Option<&StageVariables> {
+        self.extensions().get()
+    }
+
+    fn resource(&self) -> Option<&str> {
+        self.extensions()
+            .get::<Resource>()
+            .map(|resource| resource.0.as_str())
+    }
+}
@@ -1,42 +1,72 @@
 extern crate base64;
+extern crate bytes;
 extern crate failure;
 #[macro_use]
 extern crate futures;
 pub extern crate http;
+#[macro_use]
+extern crate percent_encoding;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate tokio;
 extern crate tower_service;
 
-use failure::Error;
+use std::sync::Arc;
+
 use futures::{Async, Future, Poll};
 use http::{Request, Response};
 use tower_service::{NewService, Service};
 
 mod body;
 pub use body::Body;
+mod ext;
+mod media_types;
+pub use ext::{
+    Authorizer, Elb, Identity, PathParameters, RequestContext, RequestExt, StageVariables,
+};
+pub use media_types::BinaryMediaTypes;
 mod request;
 pub use request::ApiGatewayProxyRequest;
 mod response;
 pub use response::ApiGatewayProxyResponse;
+pub mod websocket;
 
+/// Adapts a `NewService` of HTTP requests and responses to the HTTP events
+/// of API Gateway, Application Load Balancers and Function URLs.
+///
+/// The format of each event is detected when it is received,
+/// and the response is sent back in the same format.
 pub struct NewApiGatewayProxy<S> {
     new_service: S,
+    media_types: Option<Arc<BinaryMediaTypes>>,
 }
 
 impl<S> NewApiGatewayProxy<S>
 where
-    S: NewService<Error = Error, Request = Request<Body>, Response = Response<Body>>,
+    S: NewService<Request = Request<Body>, Response = Response<Body>>,
 {
     pub fn new(new_service: S) -> NewApiGatewayProxy<S> {
-        NewApiGatewayProxy { new_service }
+        NewApiGatewayProxy {
+            new_service,
+            media_types: None,
+        }
+    }
+
+    /// Set the rules that decide which responses are sent base64-encoded.
+    ///
+    /// Without them, responses with bodies created from text are sent as
+    /// text, and all others are sent base64-encoded.
+    pub fn binary_media_types(mut self, media_types: BinaryMediaTypes) -> NewApiGatewayProxy<S> {
+        self.media_types = Some(Arc::new(media_types));
+        self
     }
 }
 
 impl<S> NewService for NewApiGatewayProxy<S>
 where
-    S: NewService<Error = Error, Request = Request<Body>, Response = Response<Body>>,
+    S: NewService<Request = Request<Body>, Response = Response<Body>>,
 {
     type Future = NewApiGatewayProxyFuture<S>;
     type InitError = S::InitError;
@@ -44,22 +74,23 @@ where
     type Service = ApiGatewayProxy<S::Service>;
     type Request = ApiGatewayProxyRequest;
     type Response = ApiGatewayProxyResponse;
-    type Error = Error;
+    type Error = S::Error;
 
     fn new_service(&self) -> Self::Future {
-        NewApiGatewayProxyFuture(self.new_service.new_service())
+        NewApiGatewayProxyFuture(self.new_service.new_service(), self.media_types.clone())
     }
 }
 
 pub struct ApiGatewayProxy<S> {
     service: S,
+    media_types: Option<Arc<BinaryMediaTypes>>,
 }
 
 impl<S> Service for ApiGatewayProxy<S>
 where
-    S: Service<Error = Error, Request = Request<Body>, Response = Response<Body>>,
+    S: Service<Request = Request<Body>, Response = Response<Body>>,
 {
-    type Error = Error;
+    type Error = S::Error;
     type Request = ApiGatewayProxyRequest;
     type Response = ApiGatewayProxyResponse;
     type Future = ApiGatewayProxyFuture<S>;
@@ -69,12 +100,17 @@ where
     }
 
     fn call(&mut self, req: Self::Request) -> Self::Future {
-        let inner = self.service.call(req.0);
-        ApiGatewayProxyFuture { inner }
+        let ApiGatewayProxyRequest(http_req, format) = req;
+        let inner = self.service.call(http_req);
+        ApiGatewayProxyFuture {
+            inner,
+            format,
+            media_types: self.media_types.clone(),
+        }
     }
 }
 
-pub struct NewApiGatewayProxyFuture<S: NewService>(S::Future);
+pub struct NewApiGatewayProxyFuture<S: NewService>(S::Future, Option<Arc<BinaryMediaTypes>>);
 
 impl<S: NewService> Future for NewApiGatewayProxyFuture<S> {
     type Item = ApiGatewayProxy<S::Service>;
@@ -82,23 +118,36 @@ impl<S: NewService> Future for NewApiGatewayProxyFuture<S> {
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let service = try_ready!(self.0.poll());
-        Ok(Async::Ready(ApiGatewayProxy { service }))
+        Ok(Async::Ready(ApiGatewayProxy {
+            service,
+            media_types: self.1.clone(),
+        }))
     }
 }
 
 pub struct ApiGatewayProxyFuture<S: Service> {
     inner: S::Future,
+    format: request::PayloadFormat,
+    media_types: Option<Arc<BinaryMediaTypes>>,
 }
 
 impl<S> Future for ApiGatewayProxyFuture<S>
 where
-    S: Service<Error = Error, Request = Request<Body>, Response = Response<Body>>,
+    S: Service<Request = Request<Body>, Response = Response<Body>>,
 {
     type Item = ApiGatewayProxyResponse;
-    type Error = Error;
+    type Error = S::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let http_res = try_ready!(self.inner.poll());
-        Ok(Async::Ready(ApiGatewayProxyResponse(http_res)))
+        let binary = self
+            .media_types
+            .as_ref()
+            .map(|media_types| media_types.is_binary(http_res.headers()));
+        Ok(Async::Ready(ApiGatewayProxyResponse(
+            http_res,
+            self.format,
+            binary,
+        )))
     }
 }
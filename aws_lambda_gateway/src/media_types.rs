@@ -0,0 +1,119 @@
+use http;
+
+/// Rules that decide which responses are sent base64-encoded, like the
+/// binary media types of an API Gateway API.
+///
+/// A response is sent base64-encoded if its `Content-Type` matches one of
+/// the media types, or if it has a `Content-Encoding` such as `gzip`. Media
+/// types can contain wildcards, such as `image/*` or `*/*`. All other
+/// responses are sent as text, unless their body isn't valid UTF-8.
+///
+/// Without binary media types, the body of a response decides instead:
+/// bodies created from text are sent as text, and bodies created from
+/// bytes are sent base64-encoded.
+///
+/// ## Example
+///
+/// ```
+/// # extern crate aws_lambda_gateway;
+/// use aws_lambda_gateway::BinaryMediaTypes;
+///
+/// # fn main() {
+/// let media_types = BinaryMediaTypes::new()
+///     .insert("image/*")
+///     .insert("application/octet-stream");
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BinaryMediaTypes {
+    media_types: Vec<(String, String)>,
+}
+
+impl BinaryMediaTypes {
+    pub fn new() -> BinaryMediaTypes {
+        BinaryMediaTypes::default()
+    }
+
+    /// Send responses with the given media type base64-encoded.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `media_type` isn't of the form `type/subtype`.
+    pub fn insert(mut self, media_type: &str) -> BinaryMediaTypes {
+        let media_type = parse_media_type(media_type)
+            .unwrap_or_else(|| panic!("invalid media type: {:?}", media_type));
+        self.media_types.push(media_type);
+        self
+    }
+
+    /// Whether a response with the given headers is sent base64-encoded.
+    pub(crate) fn is_binary(&self, headers: &http::HeaderMap) -> bool {
+        let content_encoding = headers
+            .get(http::header::CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok());
+        let is_encoded = match content_encoding {
+            Some(value) => !value.trim().eq_ignore_ascii_case("identity"),
+            None => false,
+        };
+        if is_encoded {
+            return true;
+        }
+
+        let content_type = headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_media_type);
+        match content_type {
+            Some((ref type_, ref subtype)) => self
+                .media_types
+                .iter()
+                .any(|(t, s)| (t == "*" || t == type_) && (s == "*" || s == subtype)),
+            None => false,
+        }
+    }
+}
+
+/// Parse the type and subtype of a media type, ignoring its parameters.
+fn parse_media_type(media_type: &str) -> Option<(String, String)> {
+    let essence = media_type.split(';').next().unwrap_or_default();
+    let mut parts = essence.splitn(2, '/');
+    let type_ = parts.next()?.trim();
+    let subtype = parts.next()?.trim();
+    if type_.is_empty() || subtype.is_empty() {
+        return None;
+    }
+    Some((type_.to_ascii_lowercase(), subtype.to_ascii_lowercase()))
+}
+
+#[test]
+fn matches_media_types() {
+    let media_types = BinaryMediaTypes::new()
+        .insert("image/*")
+        .insert("application/octet-stream");
+    let headers = |pairs: &[(&str, &str)]| {
+        let mut headers = http::HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.insert(
+                name.parse::<http::header::HeaderName>().unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    };
+
+    assert!(media_types.is_binary(&headers(&[("Content-Type", "image/svg+xml")])));
+    assert!(media_types.is_binary(&headers(&[(
+        "Content-Type",
+        "Application/Octet-Stream; charset=binary"
+    )])));
+    assert!(!media_types.is_binary(&headers(&[("Content-Type", "application/json")])));
+    assert!(!media_types.is_binary(&headers(&[])));
+    assert!(media_types.is_binary(&headers(&[
+        ("Content-Type", "application/json"),
+        ("Content-Encoding", "gzip")
+    ])));
+    assert!(!media_types.is_binary(&headers(&[("Content-Encoding", "identity")])));
+    assert!(BinaryMediaTypes::new()
+        .insert("*/*")
+        .is_binary(&headers(&[("Content-Type", "text/plain")])));
+}
@@ -1,23 +1,227 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
 
 use base64;
+use bytes::Bytes;
 use http;
-use serde::{de::Error as DeError, de::MapAccess, de::Visitor, Deserialize, Deserializer};
+use percent_encoding::{utf8_percent_encode, EncodeSet, QUERY_ENCODE_SET};
+use serde::{
+    de::Error as DeError, de::IgnoredAny, de::MapAccess, de::SeqAccess, de::Visitor, Deserialize,
+    Deserializer,
+};
+use serde_json::{self, value::RawValue, Value};
 
 use body::Body;
+use ext::{Authorizer, Identity, PathParameters, RequestContext, Resource, StageVariables};
+
+/// Format of the event that was received, which determines
+/// the format of the response that is sent back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PayloadFormat {
+    /// REST API proxy integration (payload format 1.0).
+    V1,
+    /// HTTP API proxy integration or Function URL (payload format 2.0).
+    V2,
+    /// Application Load Balancer target, with multi-value headers
+    /// enabled or not.
+    Alb(bool),
+}
+
+impl PayloadFormat {
+    /// Detect the format of the given event, returning `None`
+    /// if it isn't an HTTP event.
+    fn detect(event: &EventProbe) -> Option<PayloadFormat> {
+        let is_v2 = match event.version {
+            Some(ref version) => version == "2.0",
+            None => false,
+        };
+        let (is_alb, is_websocket) = match event.request_context {
+            Some(ref ctx) => (ctx.elb.is_some(), ctx.connection_id.is_some()),
+            None => (false, false),
+        };
+        if is_v2 {
+            Some(PayloadFormat::V2)
+        } else if is_alb {
+            Some(PayloadFormat::Alb(event.multi_value_headers.is_some()))
+        } else if is_websocket || event.http_method.is_none() {
+            None
+        } else {
+            Some(PayloadFormat::V1)
+        }
+    }
+}
+
+/// Fields that tell the formats of HTTP events apart, and that
+/// describe other events for error messages.
+#[derive(Default)]
+struct EventProbe {
+    version: Option<String>,
+    http_method: Option<IgnoredAny>,
+    multi_value_headers: Option<IgnoredAny>,
+    request_context: Option<RequestContextProbe>,
+    records: Option<Vec<RecordProbe>>,
+    detail_type: Option<String>,
+    /// Names of all fields, or `None` if the event isn't a JSON object.
+    fields: Option<Vec<String>>,
+}
+
+impl<'de> Deserialize<'de> for EventProbe {
+    fn deserialize<D>(deserializer: D) -> Result<EventProbe, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(EventProbeVisitor)
+    }
+}
+
+struct EventProbeVisitor;
+
+impl EventProbeVisitor {
+    fn not_an_object<E>(self) -> Result<EventProbe, E> {
+        Ok(EventProbe::default())
+    }
+}
+
+impl<'de> Visitor<'de> for EventProbeVisitor {
+    type Value = EventProbe;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an event")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<EventProbe, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut probe = EventProbe::default();
+        let mut fields = Vec::new();
+        while let Some(key) = map.next_key::<Cow<'de, str>>()? {
+            match key.as_ref() {
+                "version" => probe.version = map.next_value()?,
+                "httpMethod" => probe.http_method = map.next_value()?,
+                "multiValueHeaders" => probe.multi_value_headers = map.next_value()?,
+                "requestContext" => probe.request_context = map.next_value()?,
+                "Records" => probe.records = map.next_value()?,
+                "detail-type" => probe.detail_type = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+            fields.push(key.into_owned());
+        }
+        probe.fields = Some(fields);
+        Ok(probe)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<EventProbe, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(IgnoredAny) = seq.next_element()? {}
+        self.not_an_object()
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<EventProbe, E> {
+        self.not_an_object()
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<EventProbe, E> {
+        self.not_an_object()
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<EventProbe, E> {
+        self.not_an_object()
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<EventProbe, E> {
+        self.not_an_object()
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<EventProbe, E> {
+        self.not_an_object()
+    }
+
+    fn visit_unit<E>(self) -> Result<EventProbe, E> {
+        self.not_an_object()
+    }
+}
+
+#[derive(Deserialize)]
+struct RequestContextProbe {
+    #[serde(default)]
+    elb: Option<IgnoredAny>,
+    #[serde(default, rename = "connectionId")]
+    connection_id: Option<IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+struct RecordProbe {
+    #[serde(default, rename = "eventSource", alias = "EventSource")]
+    event_source: Option<String>,
+}
+
+impl EventProbe {
+    /// Describe an event that isn't an HTTP event, for error messages.
+    fn describe(&self, event: &RawValue) -> String {
+        let record_source = self
+            .records
+            .as_ref()
+            .and_then(|records| records.first())
+            .and_then(|record| record.event_source.as_ref());
+        if let Some(source) = record_source {
+            return format!("an event from {}", source);
+        }
+        if let Some(ref ctx) = self.request_context {
+            if ctx.connection_id.is_some() {
+                return "an API Gateway WebSocket event".to_owned();
+            }
+        }
+        if let Some(ref detail_type) = self.detail_type {
+            return format!("an EventBridge event of type {:?}", detail_type);
+        }
+        match self.fields {
+            Some(ref fields) => format!("an unknown event with the fields {:?}", fields),
+            None => format!("a JSON value that isn't an event: {}", event.get()),
+        }
+    }
+}
 
 #[derive(Debug)]
-pub struct ApiGatewayProxyRequest(pub(crate) http::Request<Body>);
+pub struct ApiGatewayProxyRequest(pub(crate) http::Request<Body>, pub(crate) PayloadFormat);
 
 impl<'de> Deserialize<'de> for ApiGatewayProxyRequest {
     fn deserialize<D>(deserializer: D) -> Result<ApiGatewayProxyRequest, D::Error>
     where
         D: Deserializer<'de>,
     {
-        ApiGatewayProxyRequestDef::deserialize(deserializer)
-            .and_then(|def| def.try_into_http_request())
-            .map(ApiGatewayProxyRequest)
+        // the event is borrowed from the input so that it can be parsed once its
+        // format is known, which keeps the order of the headers and query string
+        // parameters, without copying the event
+        let event = <&RawValue>::deserialize(deserializer)?;
+        let probe = serde_json::from_str::<EventProbe>(event.get()).map_err(D::Error::custom)?;
+        let format = match PayloadFormat::detect(&probe) {
+            Some(format) => format,
+            None => {
+                return Err(D::Error::custom(format!(
+                    "expected an HTTP event from API Gateway, an Application Load Balancer \
+                     or a Function URL, but received {}",
+                    probe.describe(event)
+                )));
+            }
+        };
+        let req = match format {
+            PayloadFormat::V1 => serde_json::from_str::<ApiGatewayProxyRequestDef>(event.get())
+                .map_err(D::Error::custom)
+                .and_then(|def| def.try_into_http_request()),
+            PayloadFormat::V2 => serde_json::from_str::<ApiGatewayV2RequestDef>(event.get())
+                .map_err(D::Error::custom)
+                .and_then(|def| def.try_into_http_request()),
+            PayloadFormat::Alb(_) => serde_json::from_str::<AlbTargetRequestDef>(event.get())
+                .map_err(D::Error::custom)
+                .and_then(|def| def.try_into_http_request()),
+        };
+        req.map(|req| ApiGatewayProxyRequest(req, format))
     }
 }
 
@@ -29,46 +233,494 @@ struct ApiGatewayProxyRequestDef<'a> {
     http_method: Option<Cow<'a, str>>,
     #[serde(default)]
     headers: Option<DeserializeHeaders>,
-    #[serde(default, borrow)]
+    #[serde(default, rename = "multiValueHeaders")]
+    multi_value_headers: Option<DeserializeHeaders>,
+    #[serde(default, rename = "queryStringParameters")]
+    query_string_parameters: Option<DeserializeQuery>,
+    #[serde(default, rename = "multiValueQueryStringParameters")]
+    multi_value_query_string_parameters: Option<DeserializeQuery>,
+    #[serde(default, borrow, deserialize_with = "deserialize_optional_cow")]
     body: Option<Cow<'a, str>>,
     #[serde(default, rename = "isBase64Encoded")]
     is_base64_encoded: Option<bool>,
+    #[serde(default)]
+    resource: Option<String>,
+    #[serde(default, rename = "requestContext")]
+    request_context: Option<RequestContext>,
+    #[serde(default, rename = "pathParameters")]
+    path_parameters: Option<HashMap<String, String>>,
+    #[serde(default, rename = "stageVariables")]
+    stage_variables: Option<HashMap<String, String>>,
 }
 
 impl<'a> ApiGatewayProxyRequestDef<'a> {
     fn try_into_http_request<E: DeError>(self) -> Result<http::Request<Body>, E> {
         let mut builder = http::Request::builder();
 
-        if let Some(path) = self.path {
-            builder.uri(path.as_ref());
+        // the multi-value parameters contain all of the single-value parameters,
+        // so they take precedence if present
+        let query = self
+            .multi_value_query_string_parameters
+            .or(self.query_string_parameters)
+            .map(|DeserializeQuery(params)| encode_query(&params, QUERY_COMPONENT_ENCODE_SET))
+            .unwrap_or_default();
+
+        match (self.path, query.is_empty()) {
+            (Some(path), true) => {
+                builder.uri(path.as_ref());
+            }
+            (path, false) => {
+                let path = path.as_ref().map_or("/", |path| path.as_ref());
+                builder.uri(format!("{}?{}", path, query).as_str());
+            }
+            (None, true) => {}
         }
 
         if let Some(http_method) = self.http_method {
             builder.method(http_method.as_ref());
         }
 
-        let mut body = Body::default();
-        if let Some(raw_body) = self.body {
-            if self.is_base64_encoded.unwrap_or(false) {
-                body = Body::from(
-                    base64::decode(raw_body.as_ref().as_bytes()).map_err(|err| E::custom(err))?,
-                );
-            } else {
-                body = Body::from(raw_body.into_owned());
+        let body = decode_body(self.body, self.is_base64_encoded)?;
+        let mut req = builder.body(body).map_err(|err| E::custom(err))?;
+
+        // the multi-value headers contain all of the single-value headers,
+        // so they take precedence if present
+        if let Some(DeserializeHeaders(headers)) = self.multi_value_headers.or(self.headers) {
+            ::std::mem::replace(req.headers_mut(), headers);
+        }
+
+        let extensions = req.extensions_mut();
+        if let Some(resource) = self.resource {
+            extensions.insert(Resource(resource));
+        }
+        if let Some(request_context) = self.request_context {
+            extensions.insert(request_context);
+        }
+        if let Some(path_parameters) = self.path_parameters {
+            extensions.insert(PathParameters(path_parameters));
+        }
+        if let Some(stage_variables) = self.stage_variables {
+            extensions.insert(StageVariables(stage_variables));
+        }
+
+        Ok(req)
+    }
+}
+
+#[derive(Deserialize)]
+struct AlbTargetRequestDef<'a> {
+    #[serde(default, borrow)]
+    path: Option<Cow<'a, str>>,
+    #[serde(default, borrow, rename = "httpMethod")]
+    http_method: Option<Cow<'a, str>>,
+    #[serde(default)]
+    headers: Option<DeserializeHeaders>,
+    #[serde(default, rename = "multiValueHeaders")]
+    multi_value_headers: Option<DeserializeHeaders>,
+    #[serde(default, rename = "queryStringParameters")]
+    query_string_parameters: Option<DeserializeQuery>,
+    #[serde(default, rename = "multiValueQueryStringParameters")]
+    multi_value_query_string_parameters: Option<DeserializeQuery>,
+    #[serde(default, borrow, deserialize_with = "deserialize_optional_cow")]
+    body: Option<Cow<'a, str>>,
+    #[serde(default, rename = "isBase64Encoded")]
+    is_base64_encoded: Option<bool>,
+    #[serde(default, rename = "requestContext")]
+    request_context: Option<RequestContext>,
+}
+
+impl<'a> AlbTargetRequestDef<'a> {
+    fn try_into_http_request<E: DeError>(self) -> Result<http::Request<Body>, E> {
+        let mut builder = http::Request::builder();
+
+        // the load balancer passes the query string parameters on as they
+        // were sent by the client, so they are already percent-encoded
+        let query = self
+            .multi_value_query_string_parameters
+            .or(self.query_string_parameters)
+            .map(|DeserializeQuery(params)| encode_query(&params, QUERY_ENCODE_SET))
+            .unwrap_or_default();
+
+        let path = self.path.as_ref().map_or("/", |path| path.as_ref());
+        if query.is_empty() {
+            builder.uri(path);
+        } else {
+            builder.uri(format!("{}?{}", path, query).as_str());
+        }
+
+        if let Some(http_method) = self.http_method {
+            builder.method(http_method.as_ref());
+        }
+
+        let body = decode_body(self.body, self.is_base64_encoded)?;
+        let mut req = builder.body(body).map_err(|err| E::custom(err))?;
+
+        if let Some(DeserializeHeaders(headers)) = self.multi_value_headers.or(self.headers) {
+            *req.headers_mut() = headers;
+        }
+        if let Some(request_context) = self.request_context {
+            req.extensions_mut().insert(request_context);
+        }
+
+        Ok(req)
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiGatewayV2RequestDef<'a> {
+    #[serde(default, borrow, rename = "rawPath")]
+    raw_path: Option<Cow<'a, str>>,
+    #[serde(default, borrow, rename = "rawQueryString")]
+    raw_query_string: Option<Cow<'a, str>>,
+    #[serde(default)]
+    cookies: Option<Vec<String>>,
+    #[serde(default)]
+    headers: Option<DeserializeHeaders>,
+    #[serde(default, borrow, deserialize_with = "deserialize_optional_cow")]
+    body: Option<Cow<'a, str>>,
+    #[serde(default, rename = "isBase64Encoded")]
+    is_base64_encoded: Option<bool>,
+    #[serde(default, rename = "requestContext")]
+    request_context: Option<RequestContextV2Def>,
+    #[serde(default, rename = "pathParameters")]
+    path_parameters: Option<HashMap<String, String>>,
+    #[serde(default, rename = "stageVariables")]
+    stage_variables: Option<HashMap<String, String>>,
+}
+
+impl<'a> ApiGatewayV2RequestDef<'a> {
+    fn try_into_http_request<E: DeError>(self) -> Result<http::Request<Body>, E> {
+        let mut builder = http::Request::builder();
+
+        let path = self.raw_path.as_ref().map_or("/", |path| path.as_ref());
+        match self.raw_query_string {
+            Some(ref query) if !query.is_empty() => {
+                builder.uri(format!("{}?{}", path, query).as_str());
+            }
+            _ => {
+                builder.uri(path);
             }
         }
 
+        if let Some(ref request_context) = self.request_context {
+            if let Some(ref method) = request_context.http.method {
+                builder.method(method.as_str());
+            }
+        }
+
+        let body = decode_body(self.body, self.is_base64_encoded)?;
         let mut req = builder.body(body).map_err(|err| E::custom(err))?;
 
         if let Some(DeserializeHeaders(headers)) = self.headers {
-            ::std::mem::replace(req.headers_mut(), headers);
+            *req.headers_mut() = headers;
+        }
+        // cookies are sent separately from the headers in this format
+        if let Some(cookies) = self.cookies {
+            if !cookies.is_empty() {
+                let cookie = http::header::HeaderValue::from_shared(cookies.join("; ").into())
+                    .map_err(|err| E::custom(err))?;
+                req.headers_mut().insert(http::header::COOKIE, cookie);
+            }
+        }
+
+        let extensions = req.extensions_mut();
+        if let Some(request_context) = self.request_context {
+            extensions.insert(RequestContext::from(request_context));
+        }
+        if let Some(path_parameters) = self.path_parameters {
+            extensions.insert(PathParameters(path_parameters));
+        }
+        if let Some(stage_variables) = self.stage_variables {
+            extensions.insert(StageVariables(stage_variables));
         }
 
         Ok(req)
     }
 }
 
-struct DeserializeHeaders(http::HeaderMap<http::header::HeaderValue>);
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RequestContextV2Def {
+    #[serde(rename = "accountId")]
+    account_id: Option<String>,
+    #[serde(rename = "apiId")]
+    api_id: Option<String>,
+    #[serde(rename = "domainName")]
+    domain_name: Option<String>,
+    #[serde(rename = "requestId")]
+    request_id: Option<String>,
+    #[serde(rename = "routeKey")]
+    route_key: Option<String>,
+    stage: Option<String>,
+    http: HttpDescriptionDef,
+    authorizer: Option<AuthorizerV2Def>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct HttpDescriptionDef {
+    method: Option<String>,
+    path: Option<String>,
+    protocol: Option<String>,
+    #[serde(rename = "sourceIp")]
+    source_ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    user_agent: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct AuthorizerV2Def {
+    jwt: Option<JwtDef>,
+    lambda: Option<HashMap<String, Value>>,
+    iam: Option<IamDef>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JwtDef {
+    claims: HashMap<String, Value>,
+    scopes: Option<Vec<String>>,
+}
+
+/// Caller of a request that was authorized with IAM, such as
+/// for HTTP API routes or Function URLs that use `AWS_IAM` auth.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct IamDef {
+    #[serde(rename = "accessKey")]
+    access_key: Option<String>,
+    #[serde(rename = "accountId")]
+    account_id: Option<String>,
+    #[serde(rename = "callerId")]
+    caller_id: Option<String>,
+    #[serde(rename = "cognitoIdentity")]
+    cognito_identity: Option<CognitoIdentityDef>,
+    #[serde(rename = "principalOrgId")]
+    principal_org_id: Option<String>,
+    #[serde(rename = "userArn")]
+    user_arn: Option<String>,
+    #[serde(rename = "userId")]
+    user_id: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct CognitoIdentityDef {
+    #[serde(rename = "identityId")]
+    identity_id: Option<String>,
+    #[serde(rename = "identityPoolId")]
+    identity_pool_id: Option<String>,
+}
+
+impl From<RequestContextV2Def> for RequestContext {
+    fn from(mut def: RequestContextV2Def) -> Self {
+        let iam = def
+            .authorizer
+            .as_mut()
+            .and_then(|authorizer| authorizer.iam.take())
+            .unwrap_or_default();
+        let cognito_identity = iam.cognito_identity.unwrap_or_default();
+        let authorizer = def.authorizer.map(|authorizer| {
+            let jwt = authorizer.jwt.unwrap_or_default();
+            Authorizer {
+                principal_id: None,
+                claims: jwt.claims,
+                scopes: jwt.scopes,
+                context: authorizer.lambda.unwrap_or_default(),
+            }
+        });
+        RequestContext {
+            account_id: def.account_id,
+            api_id: def.api_id,
+            domain_name: def.domain_name,
+            request_id: def.request_id,
+            route_key: def.route_key,
+            stage: def.stage,
+            resource_path: def.http.path,
+            http_method: def.http.method,
+            protocol: def.http.protocol,
+            identity: Identity {
+                source_ip: def.http.source_ip,
+                user_agent: def.http.user_agent,
+                account_id: iam.account_id,
+                access_key: iam.access_key,
+                caller: iam.caller_id,
+                user: iam.user_id,
+                user_arn: iam.user_arn,
+                principal_org_id: iam.principal_org_id,
+                cognito_identity_id: cognito_identity.identity_id,
+                cognito_identity_pool_id: cognito_identity.identity_pool_id,
+                ..Identity::default()
+            },
+            authorizer,
+            ..RequestContext::default()
+        }
+    }
+}
+
+/// Deserialize an optional string, borrowing it from the input if it contains
+/// no escapes.
+///
+/// Serde only borrows strings into fields of type `Cow<str>` itself, and
+/// copies them into an owned string within an `Option`.
+fn deserialize_optional_cow<'de, D>(deserializer: D) -> Result<Option<Cow<'de, str>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct OptionVisitor;
+
+    impl<'de> Visitor<'de> for OptionVisitor {
+        type Value = Option<Cow<'de, str>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a string or null")
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_str(CowVisitor).map(Some)
+        }
+    }
+
+    struct CowVisitor;
+
+    impl<'de> Visitor<'de> for CowVisitor {
+        type Value = Cow<'de, str>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a string")
+        }
+
+        fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E> {
+            Ok(Cow::Borrowed(value))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value.to_owned()))
+        }
+
+        fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value))
+        }
+    }
+
+    deserializer.deserialize_option(OptionVisitor)
+}
+
+pub(crate) fn decode_body<E: DeError>(
+    body: Option<Cow<str>>,
+    is_base64_encoded: Option<bool>,
+) -> Result<Body, E> {
+    match body {
+        Some(raw_body) => {
+            if is_base64_encoded.unwrap_or(false) {
+                // decode into a buffer of the final size, which is then shared
+                // by the body instead of being copied
+                let mut bytes = Vec::with_capacity(raw_body.len() / 4 * 3);
+                base64::decode_config_buf(raw_body.as_bytes(), base64::STANDARD, &mut bytes)
+                    .map_err(|err| E::custom(err))?;
+                Ok(Body::from(Bytes::from(bytes)))
+            } else {
+                // a body with escapes has already been unescaped into an owned
+                // string, which is moved into the body, while one without them is
+                // borrowed from the event, and copied out of it once
+                match raw_body {
+                    Cow::Borrowed(text) => Ok(Body::from(text)),
+                    Cow::Owned(text) => Ok(Body::from(text)),
+                }
+            }
+        }
+        None => Ok(Body::default()),
+    }
+}
+
+define_encode_set! {
+    /// Characters that need to be encoded within a query string key or value.
+    pub QUERY_COMPONENT_ENCODE_SET = [QUERY_ENCODE_SET] | {
+        '%', '&', '=', '+', '`', '{', '}', '|', '\\', '^', '[', ']'
+    }
+}
+
+fn encode_query<E: EncodeSet + Copy>(params: &[(String, Vec<String>)], encode_set: E) -> String {
+    let mut query = String::new();
+    for (key, values) in params {
+        for value in values {
+            if !query.is_empty() {
+                query.push('&');
+            }
+            query.extend(utf8_percent_encode(key, encode_set));
+            query.push('=');
+            query.extend(utf8_percent_encode(value, encode_set));
+        }
+    }
+    query
+}
+
+/// Query string parameters, in the order in which they appear in the event.
+struct DeserializeQuery(Vec<(String, Vec<String>)>);
+
+/// Value of a map that is either single-valued or multi-valued.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<'a> {
+    #[serde(borrow)]
+    One(Cow<'a, str>),
+    #[serde(borrow)]
+    Many(Vec<Cow<'a, str>>),
+}
+
+impl<'a> OneOrMany<'a> {
+    fn into_vec(self) -> Vec<Cow<'a, str>> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DeserializeQuery {
+    fn deserialize<D>(deserializer: D) -> Result<DeserializeQuery, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MapVisitor;
+
+        impl<'de> Visitor<'de> for MapVisitor {
+            type Value = DeserializeQuery;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a query string parameter map")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut params = Vec::new();
+                while let Some((key, value)) = map.next_entry::<String, OneOrMany>()? {
+                    let values = value.into_vec().into_iter().map(Cow::into_owned).collect();
+                    params.push((key, values));
+                }
+                Ok(DeserializeQuery(params))
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor)
+    }
+}
+
+pub(crate) struct DeserializeHeaders(pub(crate) http::HeaderMap<http::header::HeaderValue>);
 
 impl<'de> Deserialize<'de> for DeserializeHeaders {
     fn deserialize<D>(deserializer: D) -> Result<DeserializeHeaders, D::Error>
@@ -89,14 +741,16 @@ impl<'de> Deserialize<'de> for DeserializeHeaders {
                 A: MapAccess<'de>,
             {
                 let mut headers = http::HeaderMap::new();
-                while let Some((map_key, map_value)) = map.next_entry::<Cow<str>, Cow<str>>()? {
+                while let Some((map_key, map_value)) = map.next_entry::<Cow<str>, OneOrMany>()? {
                     let header_name = map_key
                         .parse::<http::header::HeaderName>()
                         .map_err(|err| A::Error::custom(err))?;
-                    let header_value = http::header::HeaderValue::from_shared(
-                        map_value.into_owned().into(),
-                    ).map_err(|err| A::Error::custom(err))?;
-                    headers.append(header_name, header_value);
+                    for value in map_value.into_vec() {
+                        let header_value =
+                            http::header::HeaderValue::from_shared(value.into_owned().into())
+                                .map_err(|err| A::Error::custom(err))?;
+                        headers.append(header_name.clone(), header_value);
+                    }
                 }
                 Ok(DeserializeHeaders(headers))
             }
@@ -109,7 +763,7 @@ impl<'de> Deserialize<'de> for DeserializeHeaders {
 #[test]
 fn deserialize_simple() {
     let input = include_str!("../tests/fixtures/request_simple.json");
-    let ApiGatewayProxyRequest(req) =
+    let ApiGatewayProxyRequest(req, _) =
         ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
 
     assert_eq!(req.method(), http::Method::GET);
@@ -120,11 +774,12 @@ fn deserialize_simple() {
 #[test]
 fn deserialize_complex() {
     let input = include_str!("../tests/fixtures/request_complex.json");
-    let ApiGatewayProxyRequest(req) =
+    let ApiGatewayProxyRequest(req, _) =
         ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
 
     assert_eq!(req.method(), http::Method::POST);
     assert_eq!(req.uri().path(), "/path/to/resource");
+    assert_eq!(req.uri().query(), Some("foo=bar"));
     assert_eq!(req.body().as_str().unwrap(), "{\"test\":\"body\"}");
     assert_eq!(
         req.headers()["Accept"],
@@ -134,4 +789,254 @@ fn deserialize_complex() {
         req.headers()["Host"],
         "1234567890.execute-api.ap-southeast-2.amazonaws.com"
     );
+
+    use ext::RequestExt;
+    assert_eq!(req.resource(), Some("/{proxy+}"));
+    assert_eq!(
+        req.path_parameters().unwrap().get("proxy"),
+        Some("path/to/resource")
+    );
+    assert_eq!(req.stage_variables().unwrap().get("baz"), Some("qux"));
+    let ctx = req.request_context().unwrap();
+    assert_eq!(ctx.stage.as_ref().unwrap(), "prod");
+    assert_eq!(
+        ctx.request_id.as_ref().unwrap(),
+        "c6af9ac6-7b61-11e6-9a41-93e8deadbeef"
+    );
+    assert_eq!(ctx.identity.source_ip.as_ref().unwrap(), "127.0.0.1");
+    assert_eq!(
+        ctx.identity.user_agent.as_ref().unwrap(),
+        "Custom User Agent String"
+    );
+    assert!(ctx.authorizer.is_none());
+}
+
+#[test]
+fn deserialize_authorizer() {
+    let input = r#"{
+        "path": "/",
+        "httpMethod": "GET",
+        "requestContext": {
+            "authorizer": {
+                "principalId": "user-1",
+                "claims": {"sub": "1234"},
+                "tenant": "acme"
+            }
+        }
+    }"#;
+    let ApiGatewayProxyRequest(req, _) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    use ext::RequestExt;
+    let authorizer = req.request_context().unwrap().authorizer.as_ref().unwrap();
+    assert_eq!(authorizer.principal_id.as_ref().unwrap(), "user-1");
+    assert_eq!(authorizer.claims["sub"], "1234");
+    assert_eq!(authorizer.context["tenant"], "acme");
+    assert!(!authorizer.context.contains_key("claims"));
+}
+
+#[test]
+fn deserialize_query_string_parameters() {
+    let input = r#"{
+        "path": "/search",
+        "httpMethod": "GET",
+        "queryStringParameters": {"q": "rust & lambda", "tag": "b"},
+        "multiValueQueryStringParameters": {"q": ["rust & lambda"], "tag": ["a", "b"]}
+    }"#;
+    let ApiGatewayProxyRequest(req, _) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(req.uri().path(), "/search");
+    assert_eq!(req.uri().query(), Some("q=rust%20%26%20lambda&tag=a&tag=b"));
+}
+
+#[test]
+fn deserialize_multi_value_headers() {
+    let input = r#"{
+        "path": "/",
+        "httpMethod": "GET",
+        "headers": {"Accept": "text/html", "Cookie": "b=2"},
+        "multiValueHeaders": {"Accept": ["text/html"], "Cookie": ["a=1", "b=2"]}
+    }"#;
+    let ApiGatewayProxyRequest(req, _) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(req.headers()["Accept"], "text/html");
+    let cookies = req.headers().get_all("Cookie").iter().collect::<Vec<_>>();
+    assert_eq!(cookies, vec!["a=1", "b=2"]);
+}
+
+#[test]
+fn deserialize_http_api() {
+    let input = include_str!("../tests/fixtures/request_http_api.json");
+    let ApiGatewayProxyRequest(req, format) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(format, PayloadFormat::V2);
+    assert_eq!(req.method(), http::Method::POST);
+    assert_eq!(req.uri().path(), "/my/path");
+    assert_eq!(
+        req.uri().query(),
+        Some("parameter1=value1&parameter1=value2&parameter2=value")
+    );
+    assert_eq!(req.headers()["Content-Type"], "application/json");
+    assert_eq!(req.headers()["Cookie"], "cookie1=value1; cookie2=value2");
+    assert_eq!(req.body().as_ref(), b"{\"hello\":\"world\"}");
+
+    use ext::RequestExt;
+    let request_context = req.request_context().unwrap();
+    assert_eq!(request_context.route_key.as_ref().unwrap(), "POST /my/path");
+    assert_eq!(request_context.stage.as_ref().unwrap(), "$default");
+    assert_eq!(
+        request_context.identity.source_ip.as_ref().unwrap(),
+        "192.0.2.1"
+    );
+    let authorizer = request_context.authorizer.as_ref().unwrap();
+    assert_eq!(authorizer.claims["claim1"], "value1");
+    assert_eq!(authorizer.scopes.as_ref().unwrap(), &["scope1", "scope2"]);
+    assert_eq!(
+        req.path_parameters().unwrap().get("parameter1"),
+        Some("value1")
+    );
+    assert_eq!(
+        req.stage_variables().unwrap().get("stageVariable1"),
+        Some("value1")
+    );
+}
+
+#[test]
+fn deserialize_alb() {
+    let input = include_str!("../tests/fixtures/request_alb.json");
+    let ApiGatewayProxyRequest(req, format) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(format, PayloadFormat::Alb(false));
+    assert_eq!(req.method(), http::Method::GET);
+    assert_eq!(req.uri().path(), "/lambda");
+    assert_eq!(
+        req.uri().query(),
+        Some("query=1234ABCD&name=rust%20%26%20lambda")
+    );
+    assert_eq!(req.headers()["X-Forwarded-Port"], "80");
+
+    use ext::RequestExt;
+    let elb = req.request_context().unwrap().elb.as_ref().unwrap();
+    assert_eq!(
+        elb.target_group_arn.as_ref().unwrap(),
+        "arn:aws:elasticloadbalancing:us-east-1:123456789012:targetgroup/lambda-target/abcdef123456"
+    );
+}
+
+#[test]
+fn deserialize_alb_multi_value() {
+    let input = r#"{
+        "requestContext": {"elb": {"targetGroupArn": "arn"}},
+        "httpMethod": "GET",
+        "path": "/",
+        "multiValueQueryStringParameters": {"tag": ["a%2Bb", "c"]},
+        "multiValueHeaders": {"cookie": ["a=1", "b=2"]},
+        "body": "",
+        "isBase64Encoded": false
+    }"#;
+    let ApiGatewayProxyRequest(req, format) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(format, PayloadFormat::Alb(true));
+    assert_eq!(req.uri().query(), Some("tag=a%2Bb&tag=c"));
+    let cookies = req.headers().get_all("Cookie").iter().collect::<Vec<_>>();
+    assert_eq!(cookies, vec!["a=1", "b=2"]);
+}
+
+#[test]
+fn deserialize_function_url() {
+    let input = include_str!("../tests/fixtures/request_function_url.json");
+    let ApiGatewayProxyRequest(req, format) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(format, PayloadFormat::V2);
+    assert_eq!(req.method(), http::Method::POST);
+    assert_eq!(req.uri().path(), "/my/path");
+    assert_eq!(req.body().as_str().unwrap(), "Hello from client!");
+
+    use ext::RequestExt;
+    let request_context = req.request_context().unwrap();
+    assert_eq!(
+        request_context.domain_name.as_ref().unwrap(),
+        "<url-id>.lambda-url.us-west-2.on.aws"
+    );
+    let identity = &request_context.identity;
+    assert_eq!(identity.account_id.as_ref().unwrap(), "111122223333");
+    assert_eq!(identity.access_key.as_ref().unwrap(), "AKIA...");
+    assert_eq!(
+        identity.user_arn.as_ref().unwrap(),
+        "arn:aws:iam::111122223333:user/example-user"
+    );
+    assert!(identity.cognito_identity_id.is_none());
+    assert!(req.path_parameters().is_none());
+}
+
+#[test]
+fn deserialize_non_http_event() {
+    let input = r#"{"Records": [{"eventSource": "aws:sqs", "body": "hello"}]}"#;
+    let err = ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected an HTTP event from API Gateway, an Application Load Balancer \
+         or a Function URL, but received an event from aws:sqs"
+    );
+
+    let input = r#"{"requestContext": {"connectionId": "abc", "routeKey": "$connect"}}"#;
+    let err = ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with("but received an API Gateway WebSocket event"));
+
+    let input = r#"{"source": "custom", "detail": {"id": 1}}"#;
+    let err = ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with(r#"but received an unknown event with the fields ["source", "detail"]"#));
+
+    let input = r#"[1, {"a": 2}]"#;
+    let err = ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with(r#"but received a JSON value that isn't an event: [1, {"a": 2}]"#));
+}
+
+#[test]
+fn deserialize_borrows_bodies_without_escapes() {
+    #[derive(Deserialize)]
+    struct Event<'a> {
+        #[serde(default, borrow, deserialize_with = "deserialize_optional_cow")]
+        body: Option<Cow<'a, str>>,
+    }
+
+    let event = ::serde_json::from_str::<Event>(r#"{"body": "hello"}"#).unwrap();
+    match event.body {
+        Some(Cow::Borrowed("hello")) => {}
+        body => panic!("expected a borrowed body, got {:?}", body),
+    }
+    let event = ::serde_json::from_str::<Event>(r#"{"body": "\"hello\""}"#).unwrap();
+    match event.body {
+        Some(Cow::Owned(ref body)) if body == "\"hello\"" => {}
+        body => panic!("expected an owned body, got {:?}", body),
+    }
+    let event = ::serde_json::from_str::<Event>(r#"{"body": null}"#).unwrap();
+    assert!(event.body.is_none());
+    let event = ::serde_json::from_str::<Event>("{}").unwrap();
+    assert!(event.body.is_none());
+}
+
+#[test]
+fn deserialize_preserves_query_order() {
+    let input = r#"{
+        "path": "/search",
+        "httpMethod": "GET",
+        "queryStringParameters": {"z": "1", "a": "2"}
+    }"#;
+    let ApiGatewayProxyRequest(req, _) =
+        ::serde_json::from_str::<ApiGatewayProxyRequest>(&input).unwrap();
+
+    assert_eq!(req.uri().query(), Some("z=1&a=2"));
 }
@@ -1,15 +1,28 @@
+use std::str;
+
 use base64::display::Base64Display;
 use http;
-use serde::{ser::Error as SerError, ser::SerializeMap, Serialize, Serializer};
+use serde::{ser::Error as SerError, ser::SerializeMap, ser::SerializeSeq, Serialize, Serializer};
 
 use body::{self, Body};
+use request::PayloadFormat;
 
+/// Response to an HTTP event, in the format of the event.
+///
+/// The last field tells whether the body is sent base64-encoded,
+/// according to the `BinaryMediaTypes`, or is `None` if none were
+/// configured, in which case bodies created from text are sent as text
+/// and bodies created from bytes are sent base64-encoded.
 #[derive(Debug)]
-pub struct ApiGatewayProxyResponse(pub(crate) http::Response<Body>);
+pub struct ApiGatewayProxyResponse(
+    pub(crate) http::Response<Body>,
+    pub(crate) PayloadFormat,
+    pub(crate) Option<bool>,
+);
 
 impl Default for ApiGatewayProxyResponse {
     fn default() -> Self {
-        ApiGatewayProxyResponse(http::Response::default())
+        ApiGatewayProxyResponse(http::Response::default(), PayloadFormat::V1, None)
     }
 }
 
@@ -18,7 +31,17 @@ impl Serialize for ApiGatewayProxyResponse {
     where
         S: Serializer,
     {
-        ApiGatewayProxyResponseDef::from_http_response(&self.0).serialize(serializer)
+        match self.1 {
+            PayloadFormat::V1 => ApiGatewayProxyResponseDef::from_http_response(&self.0, self.2)
+                .serialize(serializer),
+            PayloadFormat::V2 => {
+                ApiGatewayV2ResponseDef::from_http_response(&self.0, self.2).serialize(serializer)
+            }
+            PayloadFormat::Alb(multi_value) => {
+                AlbTargetResponseDef::from_http_response(&self.0, multi_value, self.2)
+                    .serialize(serializer)
+            }
+        }
     }
 }
 
@@ -27,22 +50,112 @@ struct ApiGatewayProxyResponseDef<'a> {
     #[serde(rename = "statusCode")]
     status_code: i64,
     headers: SerializeHeaders<'a>,
+    #[serde(
+        rename = "multiValueHeaders",
+        skip_serializing_if = "SerializeMultiValueHeaders::is_empty"
+    )]
+    multi_value_headers: SerializeMultiValueHeaders<'a>,
     body: Option<SerializeBody<'a>>,
     #[serde(rename = "isBase64Encoded")]
     is_base64_encoded: bool,
 }
 
 impl<'a> ApiGatewayProxyResponseDef<'a> {
-    fn from_http_response(http_res: &'a http::Response<Body>) -> Self {
-        let (body, is_base64_encoded) = match http_res.body().0 {
-            body::Inner::Empty => (None, false),
-            body::Inner::Utf8(ref text) => (Some(SerializeBody::Utf8(text)), false),
-            body::Inner::Binary(ref bytes) => (Some(SerializeBody::Binary(bytes)), true),
-        };
+    fn from_http_response(http_res: &'a http::Response<Body>, binary: Option<bool>) -> Self {
+        let (body, is_base64_encoded) = SerializeBody::from_body(http_res.body(), binary);
 
         ApiGatewayProxyResponseDef {
             status_code: http_res.status().as_u16() as i64,
             headers: SerializeHeaders(http_res.headers()),
+            multi_value_headers: SerializeMultiValueHeaders {
+                headers: http_res.headers(),
+                all: false,
+            },
+            body,
+            is_base64_encoded,
+        }
+    }
+}
+
+/// Response to a request that was forwarded by an Application Load Balancer.
+///
+/// If multi-value headers are enabled on the target group, all of the headers
+/// are sent as such. Otherwise, multiple values of a header are joined by
+/// commas, which doesn't work for `Set-Cookie`.
+#[derive(Serialize)]
+struct AlbTargetResponseDef<'a> {
+    #[serde(rename = "statusCode")]
+    status_code: i64,
+    #[serde(rename = "statusDescription")]
+    status_description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<SerializeJoinedHeaders<'a>>,
+    #[serde(rename = "multiValueHeaders", skip_serializing_if = "Option::is_none")]
+    multi_value_headers: Option<SerializeMultiValueHeaders<'a>>,
+    body: Option<SerializeBody<'a>>,
+    #[serde(rename = "isBase64Encoded")]
+    is_base64_encoded: bool,
+}
+
+impl<'a> AlbTargetResponseDef<'a> {
+    fn from_http_response(
+        http_res: &'a http::Response<Body>,
+        multi_value: bool,
+        binary: Option<bool>,
+    ) -> Self {
+        let (body, is_base64_encoded) = SerializeBody::from_body(http_res.body(), binary);
+        let status = http_res.status();
+        let (headers, multi_value_headers) = if multi_value {
+            let headers = SerializeMultiValueHeaders {
+                headers: http_res.headers(),
+                all: true,
+            };
+            (None, Some(headers))
+        } else {
+            let headers = SerializeJoinedHeaders {
+                headers: http_res.headers(),
+                cookies: true,
+            };
+            (Some(headers), None)
+        };
+
+        AlbTargetResponseDef {
+            status_code: status.as_u16() as i64,
+            status_description: match status.canonical_reason() {
+                Some(reason) => format!("{} {}", status.as_u16(), reason),
+                None => status.as_u16().to_string(),
+            },
+            headers,
+            multi_value_headers,
+            body,
+            is_base64_encoded,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ApiGatewayV2ResponseDef<'a> {
+    #[serde(rename = "statusCode")]
+    status_code: i64,
+    headers: SerializeJoinedHeaders<'a>,
+    #[serde(skip_serializing_if = "SerializeCookies::is_empty")]
+    cookies: SerializeCookies<'a>,
+    body: Option<SerializeBody<'a>>,
+    #[serde(rename = "isBase64Encoded")]
+    is_base64_encoded: bool,
+}
+
+impl<'a> ApiGatewayV2ResponseDef<'a> {
+    fn from_http_response(http_res: &'a http::Response<Body>, binary: Option<bool>) -> Self {
+        let (body, is_base64_encoded) = SerializeBody::from_body(http_res.body(), binary);
+
+        ApiGatewayV2ResponseDef {
+            status_code: http_res.status().as_u16() as i64,
+            headers: SerializeJoinedHeaders {
+                headers: http_res.headers(),
+                cookies: false,
+            },
+            cookies: SerializeCookies(http_res.headers()),
             body,
             is_base64_encoded,
         }
@@ -54,6 +167,26 @@ enum SerializeBody<'a> {
     Binary(&'a [u8]),
 }
 
+impl<'a> SerializeBody<'a> {
+    /// Returns the body to serialize, and whether it is base64-encoded.
+    ///
+    /// Without binary media types, bodies created from bytes are binary. With
+    /// them, bodies that aren't binary are sent as text, unless they aren't
+    /// valid UTF-8.
+    fn from_body(body: &'a Body, binary: Option<bool>) -> (Option<Self>, bool) {
+        match (&body.0, binary) {
+            (body::Inner::Empty, _) => (None, false),
+            (body::Inner::Binary(_), None) | (_, Some(true)) => {
+                (Some(SerializeBody::Binary(body.as_bytes())), true)
+            }
+            _ => match body.as_str() {
+                Ok(text) => (Some(SerializeBody::Utf8(text)), false),
+                Err(_) => (Some(SerializeBody::Binary(body.as_bytes())), true),
+            },
+        }
+    }
+}
+
 impl<'a> Serialize for SerializeBody<'a> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -66,6 +199,17 @@ impl<'a> Serialize for SerializeBody<'a> {
     }
 }
 
+fn is_multi_valued(
+    headers: &http::HeaderMap<http::header::HeaderValue>,
+    key: &http::header::HeaderName,
+) -> bool {
+    headers.get_all(key).iter().nth(1).is_some()
+}
+
+/// Headers that have a single value.
+///
+/// API Gateway merges these with the `multiValueHeaders`,
+/// so multi-valued headers are only serialized as part of the latter.
 struct SerializeHeaders<'a>(&'a http::HeaderMap<http::header::HeaderValue>);
 
 impl<'a> Serialize for SerializeHeaders<'a> {
@@ -73,8 +217,11 @@ impl<'a> Serialize for SerializeHeaders<'a> {
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(self.0.keys_len()))?;
+        let mut map = serializer.serialize_map(None)?;
         for key in self.0.keys() {
+            if is_multi_valued(self.0, key) {
+                continue;
+            }
             let map_value = self.0[key].to_str().map_err(|err| S::Error::custom(err))?;
             map.serialize_entry(key.as_str(), map_value)?;
         }
@@ -82,6 +229,100 @@ impl<'a> Serialize for SerializeHeaders<'a> {
     }
 }
 
+/// Headers that have multiple values, such as `Set-Cookie`,
+/// or all of the headers if `all` is set.
+struct SerializeMultiValueHeaders<'a> {
+    headers: &'a http::HeaderMap<http::header::HeaderValue>,
+    all: bool,
+}
+
+impl<'a> SerializeMultiValueHeaders<'a> {
+    fn is_empty(&self) -> bool {
+        !self
+            .headers
+            .keys()
+            .any(|key| self.all || is_multi_valued(self.headers, key))
+    }
+}
+
+impl<'a> Serialize for SerializeMultiValueHeaders<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for key in self.headers.keys() {
+            if !self.all && !is_multi_valued(self.headers, key) {
+                continue;
+            }
+            let map_values = self
+                .headers
+                .get_all(key)
+                .iter()
+                .map(|value| value.to_str())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| S::Error::custom(err))?;
+            map.serialize_entry(key.as_str(), &map_values)?;
+        }
+        map.end()
+    }
+}
+
+/// Headers with multiple values joined by commas.
+///
+/// For HTTP API responses, `Set-Cookie` headers are serialized
+/// as `cookies` instead, and are left out unless `cookies` is set.
+struct SerializeJoinedHeaders<'a> {
+    headers: &'a http::HeaderMap<http::header::HeaderValue>,
+    cookies: bool,
+}
+
+impl<'a> Serialize for SerializeJoinedHeaders<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for key in self.headers.keys() {
+            if !self.cookies && key == http::header::SET_COOKIE {
+                continue;
+            }
+            let map_values = self
+                .headers
+                .get_all(key)
+                .iter()
+                .map(|value| value.to_str())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| S::Error::custom(err))?;
+            map.serialize_entry(key.as_str(), &map_values.join(","))?;
+        }
+        map.end()
+    }
+}
+
+/// Values of the `Set-Cookie` headers of an HTTP API response.
+struct SerializeCookies<'a>(&'a http::HeaderMap<http::header::HeaderValue>);
+
+impl<'a> SerializeCookies<'a> {
+    fn is_empty(&self) -> bool {
+        !self.0.contains_key(http::header::SET_COOKIE)
+    }
+}
+
+impl<'a> Serialize for SerializeCookies<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(None)?;
+        for value in self.0.get_all(http::header::SET_COOKIE) {
+            let value = value.to_str().map_err(|err| S::Error::custom(err))?;
+            seq.serialize_element(value)?;
+        }
+        seq.end()
+    }
+}
+
 #[test]
 fn serialize_simple() {
     let res = http::Response::builder()
@@ -89,7 +330,8 @@ fn serialize_simple() {
         .body(Default::default())
         .unwrap();
 
-    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(res)).unwrap();
+    let json =
+        ::serde_json::to_string(&ApiGatewayProxyResponse(res, PayloadFormat::V1, None)).unwrap();
 
     assert_eq!(
         json,
@@ -104,7 +346,8 @@ fn serialize_with_utf8_body() {
         .body("Hello World!".into())
         .unwrap();
 
-    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(res)).unwrap();
+    let json =
+        ::serde_json::to_string(&ApiGatewayProxyResponse(res, PayloadFormat::V1, None)).unwrap();
 
     assert_eq!(
         json,
@@ -120,3 +363,208 @@ fn serialize_default() {
         "{\"statusCode\":200,\"headers\":{},\"body\":null,\"isBase64Encoded\":false}"
     );
 }
+
+#[test]
+fn serialize_multi_value_headers() {
+    let res = http::Response::builder()
+        .status(200)
+        .header("Content-Type", "text/plain")
+        .header("Set-Cookie", "a=1")
+        .header("Set-Cookie", "b=2")
+        .body(Default::default())
+        .unwrap();
+
+    let json =
+        ::serde_json::to_string(&ApiGatewayProxyResponse(res, PayloadFormat::V1, None)).unwrap();
+
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"headers\":{\"content-type\":\"text/plain\"},\
+         \"multiValueHeaders\":{\"set-cookie\":[\"a=1\",\"b=2\"]},\
+         \"body\":null,\"isBase64Encoded\":false}"
+    );
+}
+
+#[test]
+fn serialize_http_api() {
+    let res = http::Response::builder()
+        .status(201)
+        .header("Content-Type", "text/plain")
+        .header("Vary", "Accept")
+        .header("Vary", "Origin")
+        .header("Set-Cookie", "a=1")
+        .header("Set-Cookie", "b=2")
+        .body("Hello World!".into())
+        .unwrap();
+
+    let json =
+        ::serde_json::to_string(&ApiGatewayProxyResponse(res, PayloadFormat::V2, None)).unwrap();
+
+    assert_eq!(
+        json,
+        "{\"statusCode\":201,\"headers\":{\"content-type\":\"text/plain\",\"vary\":\"Accept,Origin\"},\
+         \"cookies\":[\"a=1\",\"b=2\"],\"body\":\"Hello World!\",\"isBase64Encoded\":false}"
+    );
+}
+
+#[test]
+fn serialize_alb() {
+    let res = http::Response::builder()
+        .status(404)
+        .header("Content-Type", "text/plain")
+        .header("Vary", "Accept")
+        .header("Vary", "Origin")
+        .body("Not Found".into())
+        .unwrap();
+
+    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(
+        res,
+        PayloadFormat::Alb(false),
+        None,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        json,
+        "{\"statusCode\":404,\"statusDescription\":\"404 Not Found\",\
+         \"headers\":{\"content-type\":\"text/plain\",\"vary\":\"Accept,Origin\"},\
+         \"body\":\"Not Found\",\"isBase64Encoded\":false}"
+    );
+}
+
+#[test]
+fn serialize_alb_multi_value() {
+    let res = http::Response::builder()
+        .status(200)
+        .header("Content-Type", "text/plain")
+        .header("Set-Cookie", "a=1")
+        .header("Set-Cookie", "b=2")
+        .body(Default::default())
+        .unwrap();
+
+    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(
+        res,
+        PayloadFormat::Alb(true),
+        None,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"statusDescription\":\"200 OK\",\
+         \"multiValueHeaders\":{\"content-type\":[\"text/plain\"],\"set-cookie\":[\"a=1\",\"b=2\"]},\
+         \"body\":null,\"isBase64Encoded\":false}"
+    );
+}
+
+#[test]
+fn serialize_alb_unknown_status() {
+    let res = http::Response::builder()
+        .status(599)
+        .body(Default::default())
+        .unwrap();
+
+    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(
+        res,
+        PayloadFormat::Alb(false),
+        None,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        json,
+        "{\"statusCode\":599,\"statusDescription\":\"599\",\
+         \"headers\":{},\"body\":null,\"isBase64Encoded\":false}"
+    );
+}
+
+#[test]
+fn serialize_detected_alb() {
+    let input = r#"{
+        "requestContext": {"elb": {"targetGroupArn": "arn"}},
+        "httpMethod": "GET",
+        "path": "/",
+        "multiValueHeaders": {"accept": ["text/plain"]},
+        "body": "",
+        "isBase64Encoded": false
+    }"#;
+    let ::request::ApiGatewayProxyRequest(_, format) = ::serde_json::from_str(input).unwrap();
+    let res = http::Response::builder()
+        .status(200)
+        .header("Set-Cookie", "a=1")
+        .body(Default::default())
+        .unwrap();
+
+    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(res, format, None)).unwrap();
+
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"statusDescription\":\"200 OK\",\
+         \"multiValueHeaders\":{\"set-cookie\":[\"a=1\"]},\"body\":null,\"isBase64Encoded\":false}"
+    );
+}
+
+#[test]
+fn serialize_by_media_type() {
+    let res = http::Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(b"{}".to_vec().into())
+        .unwrap();
+    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(
+        res,
+        PayloadFormat::V1,
+        Some(false),
+    ))
+    .unwrap();
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"headers\":{\"content-type\":\"application/json\"},\
+         \"body\":\"{}\",\"isBase64Encoded\":false}"
+    );
+
+    let res = http::Response::builder()
+        .status(200)
+        .header("Content-Type", "image/svg+xml")
+        .body("<svg/>".into())
+        .unwrap();
+    let json =
+        ::serde_json::to_string(&ApiGatewayProxyResponse(res, PayloadFormat::V1, Some(true)))
+            .unwrap();
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"headers\":{\"content-type\":\"image/svg+xml\"},\
+         \"body\":\"PHN2Zy8+\",\"isBase64Encoded\":true}"
+    );
+
+    let res = http::Response::builder()
+        .status(200)
+        .body(vec![0xff, 0xfe].into())
+        .unwrap();
+    let json = ::serde_json::to_string(&ApiGatewayProxyResponse(
+        res,
+        PayloadFormat::V1,
+        Some(false),
+    ))
+    .unwrap();
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"headers\":{},\"body\":\"//4=\",\"isBase64Encoded\":true}"
+    );
+}
+
+#[test]
+fn serialize_without_media_types() {
+    let res = http::Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(b"{}".to_vec().into())
+        .unwrap();
+    let json =
+        ::serde_json::to_string(&ApiGatewayProxyResponse(res, PayloadFormat::V1, None)).unwrap();
+    assert_eq!(
+        json,
+        "{\"statusCode\":200,\"headers\":{\"content-type\":\"application/json\"},\
+         \"body\":\"e30=\",\"isBase64Encoded\":true}"
+    );
+}
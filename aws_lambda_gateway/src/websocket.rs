@@ -0,0 +1,828 @@
+//! Support for API Gateway WebSocket APIs.
+//!
+//! WebSocket APIs invoke the function with a `WebSocketEvent` whenever a
+//! client connects, sends a message or disconnects. The `Router` dispatches
+//! these events to handlers by their route key, and the `ConnectionsClient`
+//! pushes messages back to the connected clients through the `@connections`
+//! management API.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use failure::{err_msg, Error};
+use futures::{future, Async, Future, IntoFuture, Poll};
+use http::{self, Method, StatusCode, Uri};
+use percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use serde::{Deserialize, Deserializer};
+use tokio;
+use tower_service::{NewService, Service};
+
+use body::Body;
+use ext::{Authorizer, Identity};
+use request::{decode_body, DeserializeHeaders, PayloadFormat};
+use response::ApiGatewayProxyResponse;
+
+/// Type of a WebSocket event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum EventType {
+    /// A client is connecting, sent to the `$connect` route.
+    #[serde(rename = "CONNECT")]
+    Connect,
+    /// A client has sent a message.
+    #[serde(rename = "MESSAGE")]
+    Message,
+    /// A client has disconnected, sent to the `$disconnect` route.
+    #[serde(rename = "DISCONNECT")]
+    Disconnect,
+}
+
+/// Event that is sent by an API Gateway WebSocket API.
+#[derive(Debug)]
+pub struct WebSocketEvent {
+    /// Route that was selected for the event, such as `$connect` or `sendmessage`.
+    pub route_key: String,
+    pub event_type: EventType,
+    /// Id of the connection, which identifies the client in the `@connections` API.
+    pub connection_id: String,
+    pub message_id: Option<String>,
+    pub request_id: Option<String>,
+    pub api_id: Option<String>,
+    pub domain_name: Option<String>,
+    pub stage: Option<String>,
+    /// Time at which the client connected, in milliseconds since the epoch.
+    pub connected_at: Option<i64>,
+    pub identity: Identity,
+    pub authorizer: Option<Authorizer>,
+    /// Headers of the request that opened the connection, for `$connect` events.
+    pub headers: http::HeaderMap,
+    /// Query string parameters of the request that opened the connection,
+    /// for `$connect` events.
+    pub query_string_parameters: HashMap<String, String>,
+    pub body: Body,
+}
+
+impl<'de> Deserialize<'de> for WebSocketEvent {
+    fn deserialize<D>(deserializer: D) -> Result<WebSocketEvent, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let def = WebSocketEventDef::deserialize(deserializer)?;
+        let ctx = def.request_context;
+        Ok(WebSocketEvent {
+            route_key: ctx.route_key,
+            event_type: ctx.event_type,
+            connection_id: ctx.connection_id,
+            message_id: ctx.message_id,
+            request_id: ctx.request_id,
+            api_id: ctx.api_id,
+            domain_name: ctx.domain_name,
+            stage: ctx.stage,
+            connected_at: ctx.connected_at,
+            identity: ctx.identity,
+            authorizer: ctx.authorizer,
+            headers: def
+                .multi_value_headers
+                .or(def.headers)
+                .map(|DeserializeHeaders(headers)| headers)
+                .unwrap_or_default(),
+            query_string_parameters: def.query_string_parameters.unwrap_or_default(),
+            body: decode_body(def.body.map(Cow::Owned), def.is_base64_encoded)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct WebSocketEventDef {
+    #[serde(rename = "requestContext")]
+    request_context: WebSocketContextDef,
+    #[serde(default)]
+    headers: Option<DeserializeHeaders>,
+    #[serde(default, rename = "multiValueHeaders")]
+    multi_value_headers: Option<DeserializeHeaders>,
+    #[serde(default, rename = "queryStringParameters")]
+    query_string_parameters: Option<HashMap<String, String>>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default, rename = "isBase64Encoded")]
+    is_base64_encoded: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct WebSocketContextDef {
+    #[serde(rename = "routeKey")]
+    route_key: String,
+    #[serde(rename = "eventType")]
+    event_type: EventType,
+    #[serde(rename = "connectionId")]
+    connection_id: String,
+    #[serde(default, rename = "messageId")]
+    message_id: Option<String>,
+    #[serde(default, rename = "requestId")]
+    request_id: Option<String>,
+    #[serde(default, rename = "apiId")]
+    api_id: Option<String>,
+    #[serde(default, rename = "domainName")]
+    domain_name: Option<String>,
+    #[serde(default)]
+    stage: Option<String>,
+    #[serde(default, rename = "connectedAt")]
+    connected_at: Option<i64>,
+    #[serde(default)]
+    identity: Identity,
+    #[serde(default)]
+    authorizer: Option<Authorizer>,
+}
+
+type Route = Fn(WebSocketEvent) -> Box<Future<Item = http::Response<Body>, Error = Error> + Send>
+    + Send
+    + Sync;
+
+/// Dispatches WebSocket events to handlers by their route key.
+///
+/// Events for which no handler has been registered are dispatched to the
+/// `$default` handler, and fail if there is none.
+///
+/// ## Example
+///
+/// ```no_run
+/// # extern crate aws_lambda_gateway;
+/// use aws_lambda_gateway::http::Response;
+/// use aws_lambda_gateway::websocket::Router;
+///
+/// # fn main() {
+/// let router = Router::new()
+///     .route("$connect", |_event| Response::builder().status(200).body("".into()))
+///     .route("$default", |event| {
+///         Response::builder().status(200).body(event.body)
+///     });
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: HashMap<String, Arc<Route>>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Handle the events for the given route key with the given function.
+    pub fn route<F, S>(mut self, route_key: &str, f: F) -> Router
+    where
+        F: Fn(WebSocketEvent) -> S + Send + Sync + 'static,
+        S: IntoFuture<Item = http::Response<Body>>,
+        S::Error: Into<Error> + 'static,
+        S::Future: Send + 'static,
+    {
+        let route = move |event| -> Box<Future<Item = _, Error = _> + Send> {
+            Box::new(f(event).into_future().map_err(Into::into))
+        };
+        self.routes.insert(route_key.to_owned(), Arc::new(route));
+        self
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.routes.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl NewService for Router {
+    type Request = WebSocketEvent;
+    type Response = ApiGatewayProxyResponse;
+    type Error = Error;
+    type Service = Router;
+    type InitError = Error;
+    type Future = future::FutureResult<Router, Error>;
+
+    fn new_service(&self) -> Self::Future {
+        future::ok(self.clone())
+    }
+}
+
+impl Service for Router {
+    type Request = WebSocketEvent;
+    type Response = ApiGatewayProxyResponse;
+    type Error = Error;
+    type Future = Box<Future<Item = ApiGatewayProxyResponse, Error = Error> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, event: WebSocketEvent) -> Self::Future {
+        let route = self
+            .routes
+            .get(&event.route_key)
+            .or_else(|| self.routes.get("$default"));
+        match route {
+            Some(route) => Box::new(
+                route(event).map(|res| ApiGatewayProxyResponse(res, PayloadFormat::V1, None)),
+            ),
+            None => Box::new(future::err(err_msg(format!(
+                "no handler for the route {:?}",
+                event.route_key
+            )))),
+        }
+    }
+}
+
+/// Client for the `@connections` API, which sends messages to connected
+/// clients and manages their connections.
+///
+/// The client builds the requests for the API, and sends them through a
+/// transport, which is a `Service` from `http::Request` to `http::Response`.
+/// Requests to the API of a deployed WebSocket API have to be sent through
+/// a transport that supports HTTPS and signs the requests with AWS Signature
+/// Version 4. The `HttpTransport` that is used by default only supports
+/// plain HTTP, such as for local stand-ins of the API.
+///
+/// The transport is cloned into the future of every request, which waits
+/// for the transport to be ready before sending the request through it.
+///
+/// ## Example
+///
+/// ```no_run
+/// # extern crate aws_lambda_gateway;
+/// # extern crate futures;
+/// use aws_lambda_gateway::websocket::ConnectionsClient;
+/// use futures::Future;
+///
+/// # fn main() {
+/// let mut client = ConnectionsClient::new("http://localhost:3001".parse().unwrap()).unwrap();
+/// client
+///     .post_to_connection("L0SM9cOFvHcCIhw=", "Hello!")
+///     .wait()
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ConnectionsClient<T = HttpTransport> {
+    /// Endpoint of the API, without a trailing slash.
+    endpoint: String,
+    transport: T,
+}
+
+impl ConnectionsClient<HttpTransport> {
+    /// Create a client for the API at the given endpoint, such as
+    /// `http://localhost:3001`, using the plain HTTP transport.
+    ///
+    /// The host of the endpoint is resolved once, here, which blocks the
+    /// current thread. Use `HttpTransport::new` with `with_transport` to
+    /// connect to an address that has already been resolved.
+    pub fn new(endpoint: Uri) -> Result<ConnectionsClient<HttpTransport>, Error> {
+        let addr = resolve(&endpoint)?;
+        Ok(ConnectionsClient::with_transport(
+            endpoint,
+            HttpTransport::new(addr),
+        ))
+    }
+}
+
+impl<T> ConnectionsClient<T>
+where
+    T: Service<Request = http::Request<Body>, Response = http::Response<Body>> + Clone,
+    T::Error: Into<Error>,
+{
+    /// Create a client for the API at the given endpoint, using the given transport.
+    pub fn with_transport(endpoint: Uri, transport: T) -> ConnectionsClient<T> {
+        let endpoint = endpoint.to_string().trim_end_matches('/').to_owned();
+        ConnectionsClient {
+            endpoint,
+            transport,
+        }
+    }
+
+    /// Create a client for the API of the WebSocket API that sent the given event,
+    /// using the given transport.
+    ///
+    /// The endpoint of a deployed API is always an `https://` URI, and its
+    /// requests have to be signed with AWS Signature Version 4, so this needs
+    /// a transport that does both. The `HttpTransport` rejects these requests.
+    ///
+    /// Returns an error if the event doesn't contain the domain name and stage.
+    pub fn for_event(event: &WebSocketEvent, transport: T) -> Result<ConnectionsClient<T>, Error> {
+        let endpoint = match (event.domain_name.as_ref(), event.stage.as_ref()) {
+            (Some(domain_name), Some(stage)) => {
+                format!("https://{}/{}", domain_name, stage)
+            }
+            _ => {
+                return Err(err_msg(
+                    "the event doesn't contain the domain name and stage",
+                ))
+            }
+        };
+        Ok(ConnectionsClient::with_transport(
+            endpoint.parse()?,
+            transport,
+        ))
+    }
+
+    /// Send a message to the client of the given connection.
+    pub fn post_to_connection<B>(
+        &mut self,
+        connection_id: &str,
+        data: B,
+    ) -> ConnectionsFuture<T, ()>
+    where
+        B: Into<Body>,
+    {
+        self.call(Method::POST, connection_id, data.into(), |_| Ok(()))
+    }
+
+    /// Retrieve information about the given connection.
+    pub fn get_connection(&mut self, connection_id: &str) -> ConnectionsFuture<T, ConnectionInfo> {
+        self.call(Method::GET, connection_id, Body::default(), |res| {
+            Ok(res.body().decode_json()?)
+        })
+    }
+
+    /// Disconnect the client of the given connection.
+    pub fn delete_connection(&mut self, connection_id: &str) -> ConnectionsFuture<T, ()> {
+        self.call(Method::DELETE, connection_id, Body::default(), |_| Ok(()))
+    }
+
+    fn call<R>(
+        &mut self,
+        method: Method,
+        connection_id: &str,
+        body: Body,
+        parse: fn(http::Response<Body>) -> Result<R, Error>,
+    ) -> ConnectionsFuture<T, R> {
+        let uri = format!(
+            "{}/@connections/{}",
+            self.endpoint,
+            utf8_percent_encode(connection_id, PATH_SEGMENT_ENCODE_SET)
+        );
+        let req = http::Request::builder()
+            .method(method)
+            .uri(uri.as_str())
+            .body(body);
+        let state = match req {
+            Ok(req) => ConnectionsState::Waiting(self.transport.clone(), Some(req)),
+            Err(err) => ConnectionsState::Failed(Some(err.into())),
+        };
+        ConnectionsFuture {
+            state,
+            connection_id: connection_id.to_owned(),
+            parse,
+        }
+    }
+}
+
+/// Information about a connection, returned by `ConnectionsClient::get_connection`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConnectionInfo {
+    #[serde(rename = "ConnectedAt")]
+    pub connected_at: String,
+    #[serde(default, rename = "LastActiveAt")]
+    pub last_active_at: Option<String>,
+    #[serde(default, rename = "Identity")]
+    pub identity: ConnectionIdentity,
+}
+
+/// Identity of the client of a connection.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConnectionIdentity {
+    #[serde(rename = "SourceIp")]
+    pub source_ip: Option<String>,
+    #[serde(rename = "UserAgent")]
+    pub user_agent: Option<String>,
+}
+
+/// Error returned by the `ConnectionsClient` when the client of the connection
+/// has disconnected, and the connection can be discarded.
+#[derive(Debug)]
+pub struct ConnectionGone {
+    connection_id: String,
+}
+
+impl ConnectionGone {
+    /// Returns the ID of the connection that is gone.
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+}
+
+impl fmt::Display for ConnectionGone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "connection {} is gone", self.connection_id)
+    }
+}
+
+impl ::std::error::Error for ConnectionGone {}
+
+/// Future returned by the `ConnectionsClient`.
+pub struct ConnectionsFuture<T: Service, R> {
+    state: ConnectionsState<T>,
+    connection_id: String,
+    parse: fn(http::Response<Body>) -> Result<R, Error>,
+}
+
+enum ConnectionsState<T: Service> {
+    /// Waiting for the transport to be ready for the request.
+    Waiting(T, Option<T::Request>),
+    Pending(T::Future),
+    Failed(Option<Error>),
+}
+
+impl<T, R> Future for ConnectionsFuture<T, R>
+where
+    T: Service<Request = http::Request<Body>, Response = http::Response<Body>>,
+    T::Error: Into<Error>,
+{
+    type Item = R;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<R, Error> {
+        let res = loop {
+            let state = match self.state {
+                ConnectionsState::Waiting(ref mut transport, ref mut req) => {
+                    try_ready!(transport.poll_ready().map_err(Into::into));
+                    let req = req
+                        .take()
+                        .expect("polled ConnectionsFuture after completion");
+                    ConnectionsState::Pending(transport.call(req))
+                }
+                ConnectionsState::Pending(ref mut future) => {
+                    break try_ready!(future.poll().map_err(Into::into));
+                }
+                ConnectionsState::Failed(ref mut err) => {
+                    return Err(err
+                        .take()
+                        .expect("polled ConnectionsFuture after completion"))
+                }
+            };
+            self.state = state;
+        };
+        if res.status() == StatusCode::GONE {
+            return Err(ConnectionGone {
+                connection_id: self.connection_id.clone(),
+            }
+            .into());
+        }
+        if !res.status().is_success() {
+            return Err(err_msg(format!(
+                "@connections request failed with status {}: {}",
+                res.status(),
+                String::from_utf8_lossy(res.body().as_bytes())
+            )));
+        }
+        (self.parse)(res).map(Async::Ready)
+    }
+}
+
+impl<T: Service, R> fmt::Debug for ConnectionsFuture<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionsFuture")
+            .field("connection_id", &self.connection_id)
+            .finish()
+    }
+}
+
+/// Transport that sends requests over plain HTTP, without signing them.
+///
+/// This is meant for local stand-ins of the `@connections` API. Every
+/// request is sent over a new connection to the given address, using
+/// HTTP/1.0. The body of the response is delimited by its `Content-Length`
+/// header, or by the end of the connection without one; responses with a
+/// `Transfer-Encoding`, such as chunked ones, are rejected.
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+    addr: SocketAddr,
+}
+
+impl HttpTransport {
+    /// Create a transport that sends all requests to the given address.
+    pub fn new(addr: SocketAddr) -> HttpTransport {
+        HttpTransport { addr }
+    }
+}
+
+impl Service for HttpTransport {
+    type Request = http::Request<Body>;
+    type Response = http::Response<Body>;
+    type Error = Error;
+    type Future = Box<Future<Item = http::Response<Body>, Error = Error> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+        let buf = match encode_request(&req) {
+            Ok(buf) => buf,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let res = tokio::net::TcpStream::connect(&self.addr)
+            .and_then(|stream| tokio::io::write_all(stream, buf))
+            .and_then(|(stream, _)| tokio::io::read_to_end(stream, Vec::new()))
+            .map_err(Error::from)
+            .and_then(|(_, buf)| decode_response(&buf));
+        Box::new(res)
+    }
+}
+
+/// Returns the host and port of a plain HTTP URI.
+fn host_and_port(uri: &Uri) -> Result<(&str, u16), Error> {
+    if uri.scheme_part().map(|scheme| scheme.as_str()) != Some("http") {
+        return Err(err_msg(format!(
+            "the HTTP transport only supports plain HTTP, but got {}",
+            uri
+        )));
+    }
+    let host = uri.host().ok_or_else(|| err_msg("the URI has no host"))?;
+    Ok((host, uri.port().unwrap_or(80)))
+}
+
+fn resolve(uri: &Uri) -> Result<SocketAddr, Error> {
+    let addr = host_and_port(uri)?
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host could not be resolved"))?;
+    Ok(addr)
+}
+
+fn encode_request(req: &http::Request<Body>) -> Result<Vec<u8>, Error> {
+    let uri = req.uri();
+    let (host, port) = host_and_port(uri)?;
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    let mut buf = format!(
+        "{} {} HTTP/1.0\r\nHost: {}:{}\r\n",
+        req.method(),
+        path,
+        host,
+        port
+    )
+    .into_bytes();
+    for (name, value) in req.headers() {
+        buf.extend_from_slice(name.as_str().as_bytes());
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(value.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+    let body = req.body().as_bytes();
+    buf.extend_from_slice(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes());
+    buf.extend_from_slice(body);
+    Ok(buf)
+}
+
+fn decode_response(buf: &[u8]) -> Result<http::Response<Body>, Error> {
+    let invalid = || err_msg("received an invalid HTTP response");
+    let head_len = buf
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(invalid)?;
+    let head = ::std::str::from_utf8(&buf[..head_len])?;
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .ok_or_else(invalid)?;
+    let mut builder = http::Response::builder();
+    builder.status(status);
+    let mut body = &buf[head_len + 4..];
+    for line in lines {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().ok_or_else(invalid)?.trim();
+        let value = parts.next().ok_or_else(invalid)?.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(err_msg(format!(
+                "the HTTP transport doesn't support the transfer encoding {}",
+                value
+            )));
+        }
+        if name.eq_ignore_ascii_case("content-length") {
+            let len = value.parse::<usize>().map_err(|_| invalid())?;
+            body = body.get(..len).ok_or_else(invalid)?;
+        }
+        builder.header(name, value);
+    }
+    Ok(builder.body(Body::from(body))?)
+}
+
+#[test]
+fn deserialize_connect_event() {
+    let input = include_str!("../tests/fixtures/websocket_connect.json");
+    let event = ::serde_json::from_str::<WebSocketEvent>(input).unwrap();
+
+    assert_eq!(event.route_key, "$connect");
+    assert_eq!(event.event_type, EventType::Connect);
+    assert_eq!(event.connection_id, "L0SM9cOFvHcCIhw=");
+    assert_eq!(
+        event.domain_name.as_ref().unwrap(),
+        "abcdef1234.execute-api.us-east-1.amazonaws.com"
+    );
+    assert_eq!(event.stage.as_ref().unwrap(), "dev");
+    assert_eq!(event.headers["Sec-WebSocket-Version"], "13");
+    assert_eq!(event.query_string_parameters["token"], "abc");
+    assert_eq!(event.identity.source_ip.as_ref().unwrap(), "192.0.2.1");
+}
+
+#[test]
+fn router_dispatches_by_route_key() {
+    let mut router = Router::new()
+        .route("sendmessage", |event: WebSocketEvent| {
+            http::Response::builder().status(200).body(event.body)
+        })
+        .route("$default", |_| {
+            http::Response::builder().status(404).body(Body::default())
+        });
+
+    let event = |route_key: &str| WebSocketEvent {
+        route_key: route_key.to_owned(),
+        event_type: EventType::Message,
+        connection_id: "abc".to_owned(),
+        message_id: None,
+        request_id: None,
+        api_id: None,
+        domain_name: None,
+        stage: None,
+        connected_at: None,
+        identity: Identity::default(),
+        authorizer: None,
+        headers: http::HeaderMap::new(),
+        query_string_parameters: HashMap::new(),
+        body: "hello".into(),
+    };
+
+    let ApiGatewayProxyResponse(res, _, _) = router.call(event("sendmessage")).wait().unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body().as_str().unwrap(), "hello");
+    let ApiGatewayProxyResponse(res, _, _) = router.call(event("other")).wait().unwrap();
+    assert_eq!(res.status(), 404);
+
+    let mut router = Router::new();
+    let err = router.call(event("other")).wait().unwrap_err();
+    assert_eq!(err.to_string(), "no handler for the route \"other\"");
+}
+
+#[cfg(test)]
+type Recorded = (Method, String, Vec<u8>);
+
+/// Transport that records the requests that are sent through it, and is
+/// only ready once it has been polled before each request.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct Recorder {
+    requests: Arc<::std::sync::Mutex<Vec<Recorded>>>,
+    polled: bool,
+}
+
+#[cfg(test)]
+impl Service for Recorder {
+    type Request = http::Request<Body>;
+    type Response = http::Response<Body>;
+    type Error = Error;
+    type Future = future::FutureResult<http::Response<Body>, Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Error> {
+        if self.polled {
+            Ok(Async::Ready(()))
+        } else {
+            self.polled = true;
+            ::futures::task::current().notify();
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+        assert!(self.polled, "called the transport before it was ready");
+        self.polled = false;
+        let status = if req.uri().path().ends_with("gone") {
+            410
+        } else {
+            200
+        };
+        self.requests.lock().unwrap().push((
+            req.method().clone(),
+            req.uri().to_string(),
+            req.body().as_bytes().to_vec(),
+        ));
+        let body =
+            r#"{"ConnectedAt": "2020-01-01T00:00:00.000Z", "Identity": {"SourceIp": "192.0.2.1"}}"#;
+        future::result(
+            http::Response::builder()
+                .status(status)
+                .body(body.into())
+                .map_err(Error::from),
+        )
+    }
+}
+
+#[test]
+fn connections_client_builds_requests() {
+    let recorder = Recorder::default();
+    let endpoint = "http://localhost:3001/dev/".parse().unwrap();
+    let mut client = ConnectionsClient::with_transport(endpoint, recorder.clone());
+
+    client
+        .post_to_connection("L0SM9cOFvHcCIhw=", "hello")
+        .wait()
+        .unwrap();
+    let info = client.get_connection("abc").wait().unwrap();
+    assert_eq!(info.identity.source_ip.as_ref().unwrap(), "192.0.2.1");
+    client.delete_connection("abc").wait().unwrap();
+    let err = client
+        .post_to_connection("gone", "hello")
+        .wait()
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ConnectionGone>()
+            .unwrap()
+            .connection_id(),
+        "gone"
+    );
+
+    let requests = recorder.requests.lock().unwrap();
+    assert_eq!(
+        requests[0],
+        (
+            Method::POST,
+            "http://localhost:3001/dev/@connections/L0SM9cOFvHcCIhw=".to_owned(),
+            b"hello".to_vec()
+        )
+    );
+    assert_eq!(requests[1].0, Method::GET);
+    assert_eq!(requests[2].0, Method::DELETE);
+}
+
+#[test]
+fn connections_client_for_event() {
+    let input = include_str!("../tests/fixtures/websocket_connect.json");
+    let event = ::serde_json::from_str::<WebSocketEvent>(input).unwrap();
+    let recorder = Recorder::default();
+    let mut client = ConnectionsClient::for_event(&event, recorder.clone()).unwrap();
+
+    client.post_to_connection("abc", "hello").wait().unwrap();
+
+    let requests = recorder.requests.lock().unwrap();
+    assert_eq!(
+        requests[0].1,
+        "https://abcdef1234.execute-api.us-east-1.amazonaws.com/dev/@connections/abc"
+    );
+
+    let event = WebSocketEvent {
+        domain_name: None,
+        ..::serde_json::from_str::<WebSocketEvent>(input).unwrap()
+    };
+    assert!(ConnectionsClient::for_event(&event, recorder.clone()).is_err());
+}
+
+#[test]
+fn decode_http_response() {
+    let res = decode_response(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nhello").unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["Content-Type"], "text/plain");
+    assert_eq!(res.body().as_bytes(), b"hello");
+
+    let res = decode_response(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nhello").unwrap();
+    assert_eq!(res.body().as_bytes(), b"he");
+
+    assert!(decode_response(b"HTTP/1.0 200 OK\r\nContent-Length: 9\r\n\r\nhello").is_err());
+    assert!(
+        decode_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello")
+            .is_err()
+    );
+}
+
+#[test]
+fn http_transport_posts_to_local_endpoint() {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/dev", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut req = Vec::new();
+        while !req.ends_with(b"hello") {
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            req.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+        String::from_utf8(req).unwrap()
+    });
+
+    let mut client = ConnectionsClient::new(endpoint.parse().unwrap()).unwrap();
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(client.post_to_connection("abc", "hello"))
+        .unwrap();
+
+    let req = server.join().unwrap();
+    assert!(req.starts_with("POST /dev/@connections/abc HTTP/1.0\r\n"));
+    assert!(req.contains("\r\nContent-Length: 5\r\n"));
+}
@@ -0,0 +1,56 @@
+//! Compatibility with `std::future::Future`.
+//!
+//! The runtime is built on top of `futures` 0.1. This module contains
+//! helpers to run handlers that are written using `std::future::Future`
+//! (for instance using `async` blocks) on top of it.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use futures03::compat::Compat;
+
+/// Future that adapts a `std::future::Future` to a `futures` 0.1 future.
+pub type CompatFuture<F> = Compat<Pin<Box<F>>>;
+
+/// Convert a `std::future::Future` into a `futures` 0.1 future.
+///
+/// The resulting future will poll the inner future on every poll, which means
+/// that the scoped context set up by the runtime remains available across
+/// all of its suspension points.
+pub fn into_01<F, T, E>(future: F) -> CompatFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    Compat::new(Box::pin(future))
+}
+
+#[test]
+fn context_is_available_across_suspension_points() {
+    use std::task::{Context as TaskContext, Poll};
+
+    use futures::Future as Future01;
+
+    use context::Context;
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = Result<String, ()>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Self::Output> {
+            let request_id = Context::current().aws_request_id().to_owned();
+            if self.0 {
+                Poll::Ready(Ok(request_id))
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    let ctx = Context::builder().aws_request_id("test-request").build();
+    let future = ctx.scope(into_01(YieldOnce(false)));
+
+    assert_eq!(future.wait().unwrap(), "test-request");
+}
@@ -1,7 +1,12 @@
 //! Types that contain invocation metadata.
 
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::{Future, IntoFuture, Poll};
+use tokio::timer::{self, Delay};
 
 /// Information about the client application that invoked the lambda function.
 #[derive(Debug)]
@@ -13,6 +18,27 @@ pub struct Client {
 }
 
 impl Client {
+    /// Create a new set of client information.
+    pub fn new<A, B, C, D>(
+        installation_id: A,
+        app_title: B,
+        app_version_code: C,
+        app_package_name: D,
+    ) -> Client
+    where
+        A: Into<String>,
+        B: Into<String>,
+        C: Into<String>,
+        D: Into<String>,
+    {
+        Client {
+            installation_id: installation_id.into(),
+            app_title: app_title.into(),
+            app_version_code: app_version_code.into(),
+            app_package_name: app_package_name.into(),
+        }
+    }
+
     /// Installation id of the application.
     pub fn installation_id(&self) -> &str {
         &self.installation_id
@@ -43,6 +69,20 @@ pub struct ClientContext {
 }
 
 impl ClientContext {
+    /// Create a new client context from the given client information,
+    /// environment information and custom values.
+    pub fn new(
+        client: Client,
+        env: HashMap<String, String>,
+        custom: HashMap<String, String>,
+    ) -> ClientContext {
+        ClientContext {
+            client,
+            env,
+            custom,
+        }
+    }
+
     /// Client information provided by the mobile SDK.
     pub fn client(&self) -> &Client {
         &self.client
@@ -60,13 +100,21 @@ impl ClientContext {
 }
 
 /// Information about the cognito identity used by the calling application.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct CognitoIdentity {
     pub(crate) cognito_identity_id: Option<String>,
     pub(crate) cognito_identity_pool_id: Option<String>,
 }
 
 impl CognitoIdentity {
+    /// Create a new cognito identity from the given identity ID and pool ID.
+    pub fn new(id: Option<String>, pool_id: Option<String>) -> CognitoIdentity {
+        CognitoIdentity {
+            cognito_identity_id: id,
+            cognito_identity_pool_id: pool_id,
+        }
+    }
+
     /// Cognito identity ID.
     pub fn id(&self) -> Option<&str> {
         self.cognito_identity_id.as_ref().map(|s| s.as_ref())
@@ -84,6 +132,33 @@ pub(crate) struct LambdaContext {
     pub(crate) invoked_function_arn: String,
     pub(crate) identity: CognitoIdentity,
     pub(crate) client_context: Option<ClientContext>,
+    pub(crate) deadline: SystemTime,
+    pub(crate) xray_trace_id: Option<String>,
+    pub(crate) init_duration: Option<Duration>,
+    pub(crate) after_response: AfterResponse,
+}
+
+/// Work that is run after the response of an invocation has been sent.
+pub(crate) type Work = Box<Future<Item = (), Error = ()> + Send>;
+
+/// Work that has been registered through `Context::after_response`.
+#[derive(Default)]
+pub(crate) struct AfterResponse(Mutex<PendingWork>);
+
+#[derive(Default)]
+struct PendingWork {
+    work: Vec<Work>,
+    /// Set once the work has been taken by the runtime.
+    taken: bool,
+}
+
+impl fmt::Debug for AfterResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pending = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_tuple("AfterResponse")
+            .field(&pending.work.len())
+            .finish()
+    }
 }
 
 scoped_thread_local!(static CTX: Context);
@@ -108,6 +183,14 @@ impl Context {
         }
     }
 
+    /// Create a builder for a new context.
+    ///
+    /// This is useful to provide a context to code under test,
+    /// either directly or through `Context::with`.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
     /// AWS request ID associated with the request.
     pub fn aws_request_id(&self) -> &str {
         &self.inner.aws_request_id
@@ -130,18 +213,293 @@ impl Context {
         self.inner.client_context.as_ref()
     }
 
+    /// Point in time at which the invocation will time out.
+    pub fn deadline(&self) -> SystemTime {
+        self.inner.deadline
+    }
+
+    /// Time remaining until the invocation will time out.
+    ///
+    /// Returns a zero duration if the deadline has already passed.
+    pub fn remaining_time(&self) -> Duration {
+        self.inner
+            .deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_else(|_| Duration::from_secs(0))
+    }
+
+    /// Create a signal that fires the given margin before the invocation deadline.
+    ///
+    /// The returned `Cancellation` can be used as a future, which resolves once the
+    /// signal has fired, or checked synchronously using `Cancellation::is_cancelled`.
+    /// This allows the handler to wrap up its work gracefully (for instance by
+    /// returning partial results) before the invocation is terminated.
+    ///
+    /// Polling the future requires a tokio timer to be running, which is the
+    /// case for all futures run by the lambda runtime.
+    pub fn cancellation(&self, margin: Duration) -> Cancellation {
+        let remaining = self.remaining_time();
+        let at = Instant::now() + remaining.checked_sub(margin).unwrap_or_default();
+        Cancellation {
+            at,
+            delay: Delay::new(at),
+        }
+    }
+
+    /// X-Ray trace ID of the invocation, if tracing is enabled.
+    pub fn xray_trace_id(&self) -> Option<&str> {
+        self.inner.xray_trace_id.as_ref().map(|s| s.as_ref())
+    }
+
+    /// Whether this is the first invocation served by the current process.
+    pub fn is_cold_start(&self) -> bool {
+        self.inner.init_duration.is_some()
+    }
+
+    /// Time that passed between the start of the process and the first
+    /// invocation, if this is the first invocation served by the process.
+    ///
+    /// The start time of the process can only be determined on Linux. On other
+    /// platforms, the duration is measured from the creation of the runtime.
+    pub fn init_duration(&self) -> Option<Duration> {
+        self.inner.init_duration
+    }
+
+    /// Register work to be run after the response of this invocation has been sent.
+    ///
+    /// The work is started once the response has been flushed to the lambda
+    /// environment, and the runtime waits for it to complete before it accepts
+    /// the next invocation, so the work is never frozen half-way through.
+    /// Invocations that are already in flight when the work starts are not held
+    /// back, which can only happen if more than one invocation is allowed to be
+    /// in flight at once (see `RuntimeBuilder::max_in_flight`).
+    /// The time the runtime waits can be limited using
+    /// `RuntimeBuilder::after_response_budget`.
+    ///
+    /// Like the handler itself, the work can use `Context::current()`.
+    /// Work has to be registered before the handler future completes.
+    /// Work that is registered later on is dropped with a warning, as the
+    /// runtime has already collected the work of the invocation by then.
+    ///
+    /// Use `current_thread::after_response` to register work that is not `Send`.
+    pub fn after_response<F>(&self, work: F)
+    where
+        F: IntoFuture<Item = (), Error = ()>,
+        F::Future: Send + 'static,
+    {
+        let mut pending = self
+            .inner
+            .after_response
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if pending.taken {
+            warn!(
+                "dropping after-response work that was registered after invocation {} completed",
+                self.aws_request_id()
+            );
+            return;
+        }
+        pending.work.push(Box::new(work.into_future()));
+    }
+
+    /// Take the work that has been registered through `Context::after_response`.
+    ///
+    /// Work that is registered afterwards is dropped.
+    pub(crate) fn take_after_response(&self) -> Vec<Work> {
+        let mut pending = self
+            .inner
+            .after_response
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        pending.taken = true;
+        pending.work.drain(..).collect()
+    }
+
     pub(crate) fn new(lctx: LambdaContext) -> Context {
         Context {
             inner: Arc::new(lctx),
         }
     }
 
-    pub(crate) fn with<F, R>(&self, f: F) -> R
+    /// Run the given closure with this context installed as the current context.
+    ///
+    /// While the closure is running, `Context::current()` will return this context.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # extern crate aws_lambda_runtime;
+    /// # use aws_lambda_runtime::Context;
+    /// let ctx = Context::builder().aws_request_id("test-request").build();
+    ///
+    /// ctx.with(|| {
+    ///     assert_eq!(Context::current().aws_request_id(), "test-request");
+    /// });
+    /// ```
+    pub fn with<F, R>(&self, f: F) -> R
     where
         F: FnOnce() -> R,
     {
         CTX.set(self, f)
     }
+
+    /// Wrap the given future, so that this context is installed as the
+    /// current context every time the future is polled.
+    ///
+    /// This allows `Context::current()` to be used from within futures
+    /// that are polled outside of the lambda runtime task, for instance
+    /// when they are spawned onto an executor.
+    pub fn scope<F: IntoFuture>(&self, future: F) -> Scoped<F::Future> {
+        Scoped {
+            ctx: self.clone(),
+            future: future.into_future(),
+        }
+    }
+}
+
+/// Future that installs a context every time it is polled.
+///
+/// Created by `Context::scope`.
+#[derive(Debug)]
+pub struct Scoped<F> {
+    ctx: Context,
+    future: F,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        let future = &mut self.future;
+        self.ctx.with(|| future.poll())
+    }
+}
+
+/// Signal that fires a given margin before the invocation deadline.
+///
+/// Created by `Context::cancellation`.
+#[derive(Debug)]
+pub struct Cancellation {
+    at: Instant,
+    delay: Delay,
+}
+
+impl Cancellation {
+    /// Whether the signal has fired already.
+    pub fn is_cancelled(&self) -> bool {
+        Instant::now() >= self.at
+    }
+}
+
+impl Future for Cancellation {
+    type Item = ();
+    type Error = timer::Error;
+
+    fn poll(&mut self) -> Poll<(), timer::Error> {
+        self.delay.poll()
+    }
+}
+
+/// Spawn a future onto the default executor, propagating the current context.
+///
+/// The spawned future can use `Context::current()` to access the context
+/// of the invocation that spawned it.
+///
+/// ## Panics
+///
+/// This function will panic when called outside of a lambda runtime task,
+/// or outside of a tokio executor.
+pub fn spawn<F>(future: F)
+where
+    F: Future<Item = (), Error = ()> + Send + 'static,
+{
+    ::tokio::spawn(Context::current().scope(future));
+}
+
+/// Builder for a `Context`.
+///
+/// Created by `Context::builder()`.
+#[derive(Debug, Default)]
+pub struct Builder {
+    aws_request_id: String,
+    invoked_function_arn: String,
+    identity: CognitoIdentity,
+    client_context: Option<ClientContext>,
+    deadline: Option<SystemTime>,
+    xray_trace_id: Option<String>,
+    init_duration: Option<Duration>,
+}
+
+impl Builder {
+    /// Set the AWS request ID.
+    pub fn aws_request_id<T: Into<String>>(mut self, aws_request_id: T) -> Builder {
+        self.aws_request_id = aws_request_id.into();
+        self
+    }
+
+    /// Set the ARN of the function being invoked.
+    pub fn invoked_function_arn<T: Into<String>>(mut self, invoked_function_arn: T) -> Builder {
+        self.invoked_function_arn = invoked_function_arn.into();
+        self
+    }
+
+    /// Set the cognito identity of the calling application.
+    pub fn identity(mut self, identity: CognitoIdentity) -> Builder {
+        self.identity = identity;
+        self
+    }
+
+    /// Set the client context of the calling application.
+    pub fn client_context(mut self, client_context: ClientContext) -> Builder {
+        self.client_context = Some(client_context);
+        self
+    }
+
+    /// Set the point in time at which the invocation will time out.
+    ///
+    /// Defaults to three seconds after the context is built,
+    /// which matches the default timeout of a lambda function.
+    pub fn deadline(mut self, deadline: SystemTime) -> Builder {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set the X-Ray trace ID of the invocation.
+    pub fn xray_trace_id<T: Into<String>>(mut self, xray_trace_id: T) -> Builder {
+        self.xray_trace_id = Some(xray_trace_id.into());
+        self
+    }
+
+    /// Mark the invocation as the first one served by the process,
+    /// with the given initialization duration.
+    pub fn init_duration(mut self, init_duration: Duration) -> Builder {
+        self.init_duration = Some(init_duration);
+        self
+    }
+
+    /// Build the context.
+    pub fn build(self) -> Context {
+        Context::new(LambdaContext {
+            aws_request_id: self.aws_request_id,
+            invoked_function_arn: self.invoked_function_arn,
+            identity: self.identity,
+            client_context: self.client_context,
+            deadline: self
+                .deadline
+                .unwrap_or_else(|| SystemTime::now() + Duration::from_secs(3)),
+            xray_trace_id: self.xray_trace_id,
+            init_duration: self.init_duration,
+            after_response: AfterResponse::default(),
+        })
+    }
+}
+
+/// Converts a deadline given as a duration since the unix epoch into a `SystemTime`.
+pub(crate) fn deadline_from_epoch(since_epoch: Duration) -> SystemTime {
+    UNIX_EPOCH + since_epoch
 }
 
 #[test]
@@ -149,3 +507,74 @@ impl Context {
 fn context_current_panics_outside_of_task() {
     Context::current();
 }
+
+#[test]
+fn context_current_returns_installed_context() {
+    let ctx = Context::builder()
+        .aws_request_id("2ed80e4e-6196-11e8-876a-4f41bd893c42")
+        .invoked_function_arn("arn:aws:lambda:ap-southeast-2:771316043039:function:testFnGo")
+        .build();
+
+    ctx.with(|| {
+        let current = Context::current();
+        assert_eq!(current.aws_request_id(), "2ed80e4e-6196-11e8-876a-4f41bd893c42");
+        assert_eq!(
+            current.invoked_function_arn(),
+            "arn:aws:lambda:ap-southeast-2:771316043039:function:testFnGo"
+        );
+        assert!(!current.is_cold_start());
+    });
+}
+
+#[test]
+fn context_with_init_duration_is_cold_start() {
+    let ctx = Context::builder()
+        .init_duration(Duration::from_millis(250))
+        .build();
+
+    assert!(ctx.is_cold_start());
+    assert_eq!(ctx.init_duration(), Some(Duration::from_millis(250)));
+}
+
+#[test]
+fn context_scope_installs_context_when_polled() {
+    let ctx = Context::builder().aws_request_id("test-request").build();
+
+    let future = ctx.scope(::futures::future::lazy(|| {
+        Ok::<_, ()>(Context::current().aws_request_id().to_owned())
+    }));
+
+    assert_eq!(future.wait().unwrap(), "test-request");
+}
+
+#[test]
+fn context_cancellation_fires_before_deadline() {
+    let ctx = Context::builder()
+        .deadline(SystemTime::now() + Duration::from_secs(60))
+        .build();
+
+    assert!(!ctx.cancellation(Duration::from_secs(1)).is_cancelled());
+    assert!(ctx.cancellation(Duration::from_secs(120)).is_cancelled());
+}
+
+#[test]
+fn context_after_response_collects_work() {
+    let ctx = Context::builder().build();
+
+    ctx.after_response(Ok(()));
+    ctx.after_response(::futures::future::lazy(|| Ok(())));
+
+    assert_eq!(ctx.take_after_response().len(), 2);
+    assert!(ctx.take_after_response().is_empty());
+}
+
+#[test]
+fn context_after_response_drops_late_work() {
+    let ctx = Context::builder().build();
+
+    ctx.after_response(Ok(()));
+    assert_eq!(ctx.take_after_response().len(), 1);
+
+    ctx.after_response(Ok(()));
+    assert!(ctx.take_after_response().is_empty());
+}
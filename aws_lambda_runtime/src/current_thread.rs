@@ -0,0 +1,284 @@
+//! Runtime that runs all handlers on the current thread.
+//!
+//! The `Runtime` in this module is the single-threaded counterpart of
+//! `aws_lambda_runtime::Runtime`. Since invocations never leave the thread
+//! that started the runtime, neither the handler nor its futures, requests or
+//! responses need to be `Send`, which allows handlers to use `Rc`/`RefCell`
+//! based state and client libraries that are not thread-safe.
+//!
+//! Lambda functions usually only have access to a single vCPU, so this
+//! doesn't cost any parallelism in practice, and saves the time it takes
+//! to spin up a thread pool during a cold start.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # extern crate aws_lambda_runtime;
+//! # extern crate failure;
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//!
+//! use aws_lambda_runtime::current_thread::Runtime;
+//!
+//! # fn main() {
+//! let count = Rc::new(Cell::new(0));
+//!
+//! Runtime::new()
+//!     .unwrap()
+//!     .start(move |()| {
+//!         count.set(count.get() + 1);
+//!         Ok::<_, failure::Error>(count.get())
+//!     })
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+
+use failure::Error;
+use futures::{Async, Future, IntoFuture, Poll, Stream};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::runtime::current_thread::{self, Runtime as TokioRuntime, TaskExecutor};
+use tower_service::NewService;
+
+#[cfg(feature = "std-future")]
+use super::compat;
+use super::context::Context;
+use super::error::RuntimeError;
+use super::handler::{ContextHandler, Handler, StatefulHandler};
+use super::init;
+use super::invoke_error::IntoInvokeError;
+use super::runtime::RuntimeBuilder;
+use super::server::{self, Config, Server};
+
+/// Single-threaded runtime environment.
+///
+/// Created by `Runtime::new()`, or `RuntimeBuilder::build_current_thread()`
+/// to customize its settings.
+#[derive(Debug)]
+pub struct Runtime {
+    inner: TokioRuntime,
+    config: Config,
+}
+
+impl Runtime {
+    /// Create a new `Runtime`, returning any error that happened during the creation.
+    pub fn new() -> Result<Runtime, RuntimeError> {
+        RuntimeBuilder::new().build_current_thread()
+    }
+
+    pub(crate) fn with_config(config: Config) -> Result<Runtime, RuntimeError> {
+        init::mark_process_start();
+        let inner = TokioRuntime::new().map_err(RuntimeError::from_io)?;
+        Ok(Runtime { inner, config })
+    }
+
+    /// Start the runtime with the given handler function.
+    pub fn start<F, R, S>(self, f: F) -> Result<(), RuntimeError>
+    where
+        F: Fn(R) -> S + 'static,
+        S: IntoFuture,
+        S::Error: IntoInvokeError,
+        S::Item: Serialize,
+        R: DeserializeOwned + 'static,
+    {
+        self.start_service(Handler::from(f))
+    }
+
+    /// Start the runtime with the given async handler function.
+    ///
+    /// See `aws_lambda_runtime::Runtime::start_async` for details.
+    #[cfg(feature = "std-future")]
+    pub fn start_async<F, R, Fut, T, E>(self, f: F) -> Result<(), RuntimeError>
+    where
+        F: Fn(R) -> Fut + 'static,
+        Fut: ::std::future::Future<Output = Result<T, E>> + 'static,
+        T: Serialize + 'static,
+        E: IntoInvokeError + 'static,
+        R: DeserializeOwned + 'static,
+    {
+        self.start(move |req| compat::into_01(f(req)))
+    }
+
+    /// Start the runtime with the given handler function, which receives
+    /// the invocation `Context` as an explicit argument.
+    pub fn start_with_context<F, R, S>(self, f: F) -> Result<(), RuntimeError>
+    where
+        F: Fn(R, Context) -> S + 'static,
+        S: IntoFuture,
+        S::Error: IntoInvokeError,
+        S::Item: Serialize,
+        R: DeserializeOwned + 'static,
+    {
+        self.start_service(ContextHandler::from(f))
+    }
+
+    /// Start the runtime with the given initialization future and handler function.
+    ///
+    /// See `aws_lambda_runtime::Runtime::start_with_init` for details.
+    pub fn start_with_init<I, T, F, R, S>(mut self, init: I, f: F) -> Result<(), RuntimeError>
+    where
+        I: IntoFuture<Item = T, Error = Error>,
+        T: 'static,
+        F: Fn(&T, R, Context) -> S + 'static,
+        S: IntoFuture,
+        S::Error: IntoInvokeError,
+        S::Item: Serialize,
+        R: DeserializeOwned + 'static,
+    {
+        let state = self.init(init)?;
+        self.start_service(StatefulHandler::new(state, f))
+    }
+
+    /// Run the initialization future of `start_with_init` to completion.
+    pub(crate) fn init<I, T>(&mut self, init: I) -> Result<T, RuntimeError>
+    where
+        I: IntoFuture<Item = T, Error = Error>,
+    {
+        self.inner
+            .block_on(init.into_future())
+            .map_err(RuntimeError::init)
+    }
+
+    /// Start the runtime with the given `Service`.
+    pub fn start_service<S>(self, s: S) -> Result<(), RuntimeError>
+    where
+        S: NewService<InitError = Error> + 'static,
+        S::Error: IntoInvokeError,
+        S::Request: DeserializeOwned,
+        S::Response: Serialize,
+    {
+        let incoming = server::listen(&self.config)?;
+        self.serve(s, incoming)
+    }
+
+    /// Start the runtime with the given `Service`, accepting connections
+    /// from the given stream instead of listening on a TCP port.
+    ///
+    /// See `Server::new` for details.
+    pub fn serve<S, I>(mut self, s: S, incoming: I) -> Result<(), RuntimeError>
+    where
+        S: NewService<InitError = Error> + 'static,
+        S::Error: IntoInvokeError,
+        S::Request: DeserializeOwned,
+        S::Response: Serialize,
+        I: Stream<Error = io::Error>,
+        I::Item: AsyncRead + AsyncWrite + 'static,
+    {
+        let server = Server::with_config(s, incoming, self.config.clone())
+            .executor(TaskExecutor::current());
+        self.inner.block_on(server)?;
+        // wait for any futures that have been spawned by the handler
+        self.inner.run().map_err(|err| {
+            RuntimeError::from_io(io::Error::new(io::ErrorKind::Other, err.to_string()))
+        })
+    }
+}
+
+/// Spawn a future onto the current thread, propagating the current context.
+///
+/// This is the equivalent of `context::spawn` for futures that are not `Send`.
+///
+/// ## Panics
+///
+/// This function will panic when called outside of a lambda runtime task,
+/// or outside of a current-thread runtime.
+pub fn spawn<F>(future: F)
+where
+    F: Future<Item = (), Error = ()> + 'static,
+{
+    current_thread::spawn(Context::current().scope(future));
+}
+
+/// Register work that is not `Send`, to be run after the response of the
+/// current invocation has been sent.
+///
+/// This is the equivalent of `Context::after_response` for futures that are
+/// not `Send`. The work stays on the current thread, so it has to be run by
+/// a current-thread runtime.
+///
+/// ## Panics
+///
+/// This function will panic when called outside of a lambda runtime task.
+pub fn after_response<F>(work: F)
+where
+    F: IntoFuture<Item = (), Error = ()>,
+    F::Future: 'static,
+{
+    // look up the context first, so that no work is left behind in its slot
+    // when this is called outside of a lambda runtime task
+    let ctx = Context::current();
+    let id = LOCAL_WORK.with(|local| {
+        let mut local = local.borrow_mut();
+        let id = local.next_id;
+        local.next_id += 1;
+        local.work.insert(id, Box::new(work.into_future()));
+        id
+    });
+    ctx.after_response(LocalWork { id });
+}
+
+thread_local! {
+    static LOCAL_WORK: RefCell<LocalWorkSlots> = RefCell::new(LocalWorkSlots::default());
+}
+
+/// Work registered through `after_response` on the current thread.
+#[derive(Default)]
+struct LocalWorkSlots {
+    next_id: usize,
+    work: HashMap<usize, Box<Future<Item = (), Error = ()>>>,
+}
+
+/// Handle to work registered through `after_response`, which can be passed
+/// to `Context::after_response` while the work itself stays on its thread.
+struct LocalWork {
+    id: usize,
+}
+
+impl Future for LocalWork {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        // take the work out of its slot while polling it,
+        // as it may register further work itself
+        let work = LOCAL_WORK.with(|local| local.borrow_mut().work.remove(&self.id));
+        let mut work = match work {
+            Some(work) => work,
+            None => {
+                error!("after-response work was polled outside of the thread it was registered on");
+                return Ok(Async::Ready(()));
+            }
+        };
+        let result = work.poll();
+        if let Ok(Async::NotReady) = result {
+            LOCAL_WORK.with(|local| local.borrow_mut().work.insert(self.id, work));
+        }
+        result
+    }
+}
+
+impl Drop for LocalWork {
+    fn drop(&mut self) {
+        let id = self.id;
+        // drop the work outside of the borrow, in case it holds further work
+        let work = LOCAL_WORK
+            .try_with(|local| local.borrow_mut().work.remove(&id))
+            .ok();
+        drop(work);
+    }
+}
+
+#[test]
+fn after_response_outside_of_a_task_leaves_no_work_behind() {
+    use std::panic;
+
+    let result = panic::catch_unwind(|| after_response(Ok(())));
+
+    assert!(result.is_err());
+    assert!(LOCAL_WORK.with(|local| local.borrow().work.is_empty()));
+}
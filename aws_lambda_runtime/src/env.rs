@@ -6,12 +6,19 @@
 lazy_static! {
     static ref AWS_LAMBDA_FUNCTION_NAME: String = var("AWS_LAMBDA_FUNCTION_NAME");
     static ref AWS_LAMBDA_FUNCTION_VERSION: String = var("AWS_LAMBDA_FUNCTION_VERSION");
-    static ref AWS_LAMBDA_FUNCTION_MEMORY_SIZE: usize = {
-        let size_in_mb = var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")
-            .parse::<usize>()
-            .unwrap_or_else(|err| panic!("AWS_LAMBDA_FUNCTION_MEMORY_SIZE: {}", err));
-        size_in_mb * 1024 * 1024
-    };
+    static ref AWS_LAMBDA_FUNCTION_MEMORY_SIZE: Option<usize> =
+        ::std::env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")
+            .ok()
+            .and_then(|size_in_mb| size_in_mb.parse::<usize>().ok())
+            .map(|size_in_mb| size_in_mb * 1024 * 1024);
+    static ref AWS_LAMBDA_INITIALIZATION_TYPE: InitializationType =
+        match ::std::env::var("AWS_LAMBDA_INITIALIZATION_TYPE") {
+            Ok(ref ty) if ty == "provisioned-concurrency" => {
+                InitializationType::ProvisionedConcurrency
+            }
+            Ok(ref ty) if ty == "snap-start" => InitializationType::SnapStart,
+            _ => InitializationType::OnDemand,
+        };
 }
 
 fn var(key: &str) -> String {
@@ -29,6 +36,54 @@ pub fn function_version() -> &'static str {
 }
 
 /// Returns the memory limit (in bytes) of the current function.
+///
+/// ## Panics
+///
+/// This function will panic if the limit is not provided by the environment,
+/// see `try_function_memory_size` for a non-panicking version.
 pub fn function_memory_size() -> usize {
+    try_function_memory_size()
+        .unwrap_or_else(|| panic!("AWS_LAMBDA_FUNCTION_MEMORY_SIZE: not a valid memory size"))
+}
+
+/// Returns the memory limit (in bytes) of the current function, or `None`
+/// if the `AWS_LAMBDA_FUNCTION_MEMORY_SIZE` variable does not specify a valid size.
+pub fn try_function_memory_size() -> Option<usize> {
     *AWS_LAMBDA_FUNCTION_MEMORY_SIZE
 }
+
+/// The way in which the environment of the current function was initialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitializationType {
+    /// The environment was started on demand, to serve an incoming invocation.
+    OnDemand,
+    /// The environment was started ahead of time using provisioned concurrency.
+    ProvisionedConcurrency,
+    /// The environment was restored from a snapshot.
+    SnapStart,
+}
+
+impl InitializationType {
+    /// Returns the name of the initialization type, as reported by lambda.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            InitializationType::OnDemand => "on-demand",
+            InitializationType::ProvisionedConcurrency => "provisioned-concurrency",
+            InitializationType::SnapStart => "snap-start",
+        }
+    }
+}
+
+impl ::std::fmt::Display for InitializationType {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returns how the environment of the current function was initialized.
+///
+/// Environments that do not report an initialization type are assumed to
+/// have been started on demand.
+pub fn initialization_type() -> InitializationType {
+    *AWS_LAMBDA_INITIALIZATION_TYPE
+}
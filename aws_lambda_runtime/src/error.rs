@@ -1,5 +1,7 @@
 use std::fmt;
 
+use failure::Error;
+
 /// Error that can occur in the runtime.
 #[derive(Debug)]
 pub struct RuntimeError {
@@ -18,6 +20,12 @@ impl RuntimeError {
             inner: RuntimeErrorInner::Environment(reason),
         }
     }
+
+    pub(crate) fn init(err: Error) -> Self {
+        RuntimeError {
+            inner: RuntimeErrorInner::Init(err),
+        }
+    }
 }
 
 impl fmt::Display for RuntimeError {
@@ -25,6 +33,7 @@ impl fmt::Display for RuntimeError {
         match self.inner {
             RuntimeErrorInner::Io(ref err) => write!(f, "i/o error: {}", err),
             RuntimeErrorInner::Environment(reason) => write!(f, "environment error: {}", reason),
+            RuntimeErrorInner::Init(ref err) => write!(f, "initialization error: {}", err),
         }
     }
 }
@@ -34,6 +43,7 @@ impl ::std::error::Error for RuntimeError {
         match self.inner {
             RuntimeErrorInner::Io(ref err) => Some(err),
             RuntimeErrorInner::Environment(_) => None,
+            RuntimeErrorInner::Init(_) => None,
         }
     }
 }
@@ -42,6 +52,7 @@ impl ::std::error::Error for RuntimeError {
 enum RuntimeErrorInner {
     Io(::std::io::Error),
     Environment(&'static str),
+    Init(Error),
 }
 
 #[derive(Debug)]
@@ -78,3 +89,25 @@ impl From<::void::Void> for ConnectionError {
         ::void::unreachable(err)
     }
 }
+
+#[test]
+fn init_error_is_returned_from_start_with_init() {
+    use failure::err_msg;
+    use futures::future;
+
+    use current_thread::Runtime;
+    use context::Context;
+
+    let err = Runtime::new()
+        .unwrap()
+        .start_with_init(future::err::<(), _>(err_msg("missing config")), |_, (), _: Context| {
+            Ok::<_, Error>(())
+        })
+        .unwrap_err();
+
+    match err.inner {
+        RuntimeErrorInner::Init(ref err) => assert_eq!(err.to_string(), "missing config"),
+        ref inner => panic!("unexpected error: {:?}", inner),
+    }
+    assert_eq!(err.to_string(), "initialization error: missing config");
+}
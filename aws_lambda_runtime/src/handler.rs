@@ -5,6 +5,8 @@ use failure::Error;
 use futures::{future::FutureResult, Async, IntoFuture, Poll};
 use tower_service::{NewService, Service};
 
+use super::context::Context;
+
 /// Wrapper to convert a `Fn` into a `NewService`.
 #[derive(Debug)]
 pub struct Handler<F, R> {
@@ -69,3 +71,160 @@ where
         (self.f)(req).into_future()
     }
 }
+
+/// Wrapper to convert a `Fn` that takes an explicit `Context` into a `NewService`.
+#[derive(Debug)]
+pub struct ContextHandler<F, R> {
+    f: Arc<F>,
+    _phan: PhantomData<fn() -> R>,
+}
+
+impl<F, R, S> From<F> for ContextHandler<F, R>
+where
+    F: Fn(R, Context) -> S,
+    S: IntoFuture,
+{
+    fn from(f: F) -> Self {
+        ContextHandler {
+            f: Arc::new(f),
+            _phan: PhantomData,
+        }
+    }
+}
+
+impl<F, R, S> NewService for ContextHandler<F, R>
+where
+    F: Fn(R, Context) -> S,
+    S: IntoFuture,
+{
+    type Request = R;
+    type Response = S::Item;
+    type Error = S::Error;
+    type InitError = Error;
+    type Future = FutureResult<Self::Service, Error>;
+    type Service = ContextHandlerService<F, R>;
+
+    fn new_service(&self) -> FutureResult<Self::Service, Error> {
+        Ok(ContextHandlerService {
+            f: self.f.clone(),
+            _phan: PhantomData,
+        }).into()
+    }
+}
+
+#[derive(Debug)]
+pub struct ContextHandlerService<F, R> {
+    f: Arc<F>,
+    _phan: PhantomData<fn() -> R>,
+}
+
+impl<F, R, S> Service for ContextHandlerService<F, R>
+where
+    F: Fn(R, Context) -> S,
+    S: IntoFuture,
+{
+    type Request = R;
+    type Response = S::Item;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        (self.f)(req, Context::current()).into_future()
+    }
+}
+
+/// Wrapper to convert a `Fn` that operates on shared state into a `NewService`.
+///
+/// The state is shared between all services created from this wrapper,
+/// and passed by reference into the function on every invocation,
+/// alongside the current `Context`.
+#[derive(Debug)]
+pub struct StatefulHandler<T, F, R> {
+    state: Arc<T>,
+    f: Arc<F>,
+    _phan: PhantomData<fn() -> R>,
+}
+
+impl<T, F, R, S> StatefulHandler<T, F, R>
+where
+    F: Fn(&T, R, Context) -> S,
+    S: IntoFuture,
+{
+    /// Create a new handler from the given state and function.
+    pub fn new(state: T, f: F) -> Self {
+        StatefulHandler {
+            state: Arc::new(state),
+            f: Arc::new(f),
+            _phan: PhantomData,
+        }
+    }
+}
+
+impl<T, F, R, S> NewService for StatefulHandler<T, F, R>
+where
+    F: Fn(&T, R, Context) -> S,
+    S: IntoFuture,
+{
+    type Request = R;
+    type Response = S::Item;
+    type Error = S::Error;
+    type InitError = Error;
+    type Future = FutureResult<Self::Service, Error>;
+    type Service = StatefulHandlerService<T, F, R>;
+
+    fn new_service(&self) -> FutureResult<Self::Service, Error> {
+        Ok(StatefulHandlerService {
+            state: self.state.clone(),
+            f: self.f.clone(),
+            _phan: PhantomData,
+        }).into()
+    }
+}
+
+#[derive(Debug)]
+pub struct StatefulHandlerService<T, F, R> {
+    state: Arc<T>,
+    f: Arc<F>,
+    _phan: PhantomData<fn() -> R>,
+}
+
+impl<T, F, R, S> Service for StatefulHandlerService<T, F, R>
+where
+    F: Fn(&T, R, Context) -> S,
+    S: IntoFuture,
+{
+    type Request = R;
+    type Response = S::Item;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        (self.f)(&self.state, req, Context::current()).into_future()
+    }
+}
+
+#[test]
+fn context_handler_receives_built_context() {
+    use futures::Future;
+
+    let handler = ContextHandler::from(|name: String, ctx: Context| {
+        Ok::<_, Error>(format!("{} from {}", name, ctx.aws_request_id()))
+    });
+    let mut service = handler.new_service().wait().unwrap();
+
+    let ctx = Context::builder().aws_request_id("test-request").build();
+    let response = ctx
+        .with(|| service.call("hello".to_owned()))
+        .wait()
+        .unwrap();
+
+    assert_eq!(response, "hello from test-request");
+}
@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref PROCESS_START: Instant = {
+        let now = Instant::now();
+        process_age()
+            .and_then(|age| now.checked_sub(age))
+            .unwrap_or(now)
+    };
+}
+
+static INVOKED: AtomicBool = AtomicBool::new(false);
+
+/// Clock ticks per second, in which `/proc` reports times (`USER_HZ`).
+/// This is fixed at 100 on all architectures that lambda runs on.
+const CLOCK_TICKS_PER_SEC: f64 = 100.0;
+
+/// Record the start of the process.
+///
+/// On Linux, the start time of the process is read from `/proc/self/stat`.
+/// There is no portable way to find out when the process was started on
+/// other platforms, so there it is approximated by the first call to this
+/// function, which happens as soon as the runtime is created.
+pub(crate) fn mark_process_start() {
+    ::lazy_static::initialize(&PROCESS_START);
+}
+
+/// Record the start of an invocation.
+///
+/// Returns the time that has passed since the start of the process
+/// if this is the first invocation, and `None` otherwise.
+pub(crate) fn mark_invocation_start() -> Option<Duration> {
+    if INVOKED.swap(true, Ordering::SeqCst) {
+        None
+    } else {
+        Some(PROCESS_START.elapsed())
+    }
+}
+
+/// Returns the time that has passed since the start of the process,
+/// if it can be determined on the current platform.
+fn process_age() -> Option<Duration> {
+    let mut stat = String::new();
+    File::open("/proc/self/stat")
+        .and_then(|mut file| file.read_to_string(&mut stat))
+        .ok()?;
+    let mut uptime = String::new();
+    File::open("/proc/uptime")
+        .and_then(|mut file| file.read_to_string(&mut uptime))
+        .ok()?;
+
+    let started = parse_start_time(&stat)? as f64 / CLOCK_TICKS_PER_SEC;
+    let age = parse_uptime(&uptime)? - started;
+    if age < 0.0 {
+        return None;
+    }
+    Some(Duration::from_millis((age * 1e3) as u64))
+}
+
+/// Parses the start time of the process, in clock ticks after system boot.
+fn parse_start_time(stat: &str) -> Option<u64> {
+    // the command name may contain spaces and parentheses,
+    // so the fields are counted from the end of the name
+    let fields = &stat[stat.rfind(')')? + 1..];
+    // the start time is the 22nd field, and the name is the 2nd one
+    fields.split_whitespace().nth(22 - 3)?.parse().ok()
+}
+
+/// Parses the time since system boot in seconds.
+fn parse_uptime(uptime: &str) -> Option<f64> {
+    uptime.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_start_time, parse_uptime, process_age};
+
+    #[test]
+    fn parses_start_time_from_stat() {
+        let stat = "4242 (boot strap) (x)) S 1 4242 4242 0 -1 4194560 1520 0 0 0 2 1 0 0 \
+                    20 0 1 0 123456 10285056 803 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 \
+                    17 0 0 0 0 0 0\n";
+        assert_eq!(parse_start_time(stat), Some(123_456));
+        assert_eq!(parse_start_time("4242 (bootstrap) S 1"), None);
+    }
+
+    #[test]
+    fn parses_uptime() {
+        assert_eq!(parse_uptime("1234.56 4321.00\n"), Some(1234.56));
+        assert_eq!(parse_uptime(""), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_age_is_determined_on_linux() {
+        // the test process has just been started
+        assert!(process_age().unwrap() < Duration::from_secs(600));
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+
+use failure::Error;
+
+/// Errors that can be reported back to lambda as the result of an invocation.
+///
+/// The message of the error is rendered using its `Display` implementation.
+/// The remaining methods control the type name and stack trace that are
+/// included in the response, and have sensible defaults. This means that
+/// most error types can opt in with an empty `impl` block:
+///
+/// ```
+/// # extern crate aws_lambda_runtime;
+/// use std::fmt;
+///
+/// use aws_lambda_runtime::IntoInvokeError;
+///
+/// #[derive(Debug)]
+/// struct NotFound(String);
+///
+/// impl fmt::Display for NotFound {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "{} was not found", self.0)
+///     }
+/// }
+///
+/// impl IntoInvokeError for NotFound {
+///     fn error_type(&self) -> &str {
+///         "NotFound"
+///     }
+/// }
+/// # fn main() {}
+/// ```
+pub trait IntoInvokeError: fmt::Display {
+    /// Name of the type of the error.
+    ///
+    /// Defaults to `"Error"`.
+    fn error_type(&self) -> &str {
+        "Error"
+    }
+
+    /// Backtrace of the error, used to extract a stack trace.
+    ///
+    /// The backtrace is expected to be rendered in the format
+    /// of the `backtrace` crate. Defaults to no backtrace.
+    fn backtrace(&self) -> Option<&fmt::Display> {
+        None
+    }
+}
+
+impl IntoInvokeError for Error {
+    fn backtrace(&self) -> Option<&fmt::Display> {
+        Some(Error::backtrace(self))
+    }
+}
+
+impl IntoInvokeError for Box<::std::error::Error + Send + Sync> {}
+
+impl IntoInvokeError for ::std::io::Error {}
+
+impl IntoInvokeError for String {}
@@ -22,6 +22,8 @@ extern crate bytes;
 extern crate failure;
 #[macro_use]
 extern crate futures;
+#[cfg(feature = "std-future")]
+extern crate futures03;
 extern crate gob;
 #[macro_use]
 extern crate lazy_static;
@@ -43,14 +45,23 @@ extern crate void;
 
 mod error;
 mod handler;
+mod init;
+mod invoke_error;
 mod proto;
+mod recycle;
 mod runtime;
 mod server;
 
+#[cfg(feature = "std-future")]
+pub mod compat;
 pub mod context;
+pub mod current_thread;
 pub mod env;
+pub mod memory;
 
 pub use context::Context;
 pub use error::RuntimeError;
-pub use handler::Handler;
-pub use runtime::Runtime;
+pub use handler::{ContextHandler, Handler, StatefulHandler};
+pub use invoke_error::IntoInvokeError;
+pub use runtime::{Runtime, RuntimeBuilder};
+pub use server::Server;
@@ -0,0 +1,193 @@
+//! Tracking of heap memory usage.
+//!
+//! This module contains a global allocator that keeps track of the current
+//! and peak heap usage of the process. Once it is installed, the runtime
+//! includes the peak usage of every invocation in the invocation summary,
+//! and can warn when the usage crosses a given ratio of the function's
+//! memory limit (see `RuntimeBuilder::memory_warning`).
+//!
+//! ## Example
+//!
+//! ```no_run
+//! extern crate aws_lambda_runtime;
+//!
+//! use aws_lambda_runtime::memory::TrackingAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+//!
+//! fn main() {
+//!     // start the runtime as usual
+//! }
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static COUNTERS: Counters = Counters::new();
+
+/// Global allocator that tracks the heap usage of the process.
+///
+/// Allocations are delegated to the system allocator.
+#[derive(Debug)]
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            COUNTERS.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            COUNTERS.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        COUNTERS.record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                COUNTERS.record_alloc(new_size - layout.size());
+            } else {
+                COUNTERS.record_dealloc(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// Heap usage that has been recorded by an allocator.
+struct Counters {
+    /// Whether anything has been allocated, which tells
+    /// whether the allocator is installed.
+    installed: AtomicBool,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Counters {
+    const fn new() -> Counters {
+        Counters {
+            installed: AtomicBool::new(false),
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    fn record_alloc(&self, size: usize) {
+        if !self.installed.load(Ordering::Relaxed) {
+            self.installed.store(true, Ordering::Relaxed);
+        }
+        let current = self.current.fetch_add(size, Ordering::Relaxed) + size;
+        let mut peak = self.peak.load(Ordering::Relaxed);
+        while current > peak {
+            match self.peak.compare_exchange_weak(
+                peak,
+                current,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => peak = actual,
+            }
+        }
+    }
+
+    fn record_dealloc(&self, size: usize) {
+        self.current.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    fn current_usage(&self) -> Option<usize> {
+        if self.installed.load(Ordering::Relaxed) {
+            Some(self.current.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+
+    fn peak_usage(&self) -> Option<usize> {
+        if self.installed.load(Ordering::Relaxed) {
+            Some(self.peak.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+
+    fn reset_peak(&self) {
+        self.peak
+            .store(self.current.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+/// Returns the number of bytes that are currently allocated on the heap,
+/// or `None` if the `TrackingAllocator` is not installed.
+pub fn current_usage() -> Option<usize> {
+    COUNTERS.current_usage()
+}
+
+/// Returns the highest number of bytes that have been allocated on the heap
+/// since the start of the current invocation, or `None` if the
+/// `TrackingAllocator` is not installed.
+///
+/// When invocations run concurrently, this covers all of them, and it is
+/// reset whenever one of them starts. It is only accurate for a single
+/// invocation if at most one is in flight (see `RuntimeBuilder::max_in_flight`).
+pub fn peak_usage() -> Option<usize> {
+    COUNTERS.peak_usage()
+}
+
+/// Reset the peak usage to the current usage, at the start of an invocation.
+pub(crate) fn reset_peak() {
+    COUNTERS.reset_peak();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout};
+
+    use super::{current_usage, Counters, TrackingAllocator};
+
+    #[test]
+    fn tracks_current_and_peak_usage() {
+        let counters = Counters::new();
+        assert_eq!(counters.current_usage(), None);
+        assert_eq!(counters.peak_usage(), None);
+
+        counters.record_alloc(1024);
+        assert_eq!(counters.current_usage(), Some(1024));
+        counters.record_alloc(3072);
+        assert_eq!(counters.current_usage(), Some(4096));
+        counters.record_dealloc(4096);
+        assert_eq!(counters.current_usage(), Some(0));
+        assert_eq!(counters.peak_usage(), Some(4096));
+
+        // a reset without any live allocations keeps reporting usage
+        counters.reset_peak();
+        assert_eq!(counters.peak_usage(), Some(0));
+
+        counters.record_alloc(1024);
+        counters.reset_peak();
+        assert_eq!(counters.peak_usage(), Some(1024));
+    }
+
+    #[test]
+    fn allocator_records_usage() {
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        unsafe {
+            let ptr = TrackingAllocator.alloc(layout);
+            TrackingAllocator.dealloc(ptr, layout);
+        }
+        assert!(current_usage().is_some());
+    }
+}
@@ -11,6 +11,7 @@ use super::super::error::ConnectionError;
 use super::messages;
 use super::payload::PayloadDeserializer;
 use context;
+use init;
 
 #[derive(Deserialize)]
 #[serde(tag = "ServiceMethod")]
@@ -115,15 +116,25 @@ where
             cognito_identity_pool_id: message.cognito_identity_pool_id,
         };
 
+        let deadline = Duration::new(message.deadline.secs as u64, message.deadline.nanos as u32);
+
+        let xray_trace_id = if message.x_amzn_trace_id.is_empty() {
+            None
+        } else {
+            Some(message.x_amzn_trace_id.to_owned())
+        };
+
         let ctx = context::Context::new(context::LambdaContext {
             aws_request_id: message.request_id,
             invoked_function_arn: message.invoked_function_arn,
             identity: identity,
             client_context: None,
+            deadline: context::deadline_from_epoch(deadline),
+            xray_trace_id,
+            init_duration: init::mark_invocation_start(),
+            after_response: Default::default(),
         });
 
-        let deadline = Duration::new(message.deadline.secs as u64, message.deadline.nanos as u32);
-
         let payload = T::deserialize(PayloadDeserializer::new(message.payload.as_ref()))
             .map_err(|err| DecodeError::User(seq, err.into()))?;
 
@@ -152,6 +163,53 @@ where
     }
 }
 
+/// Requests as they are sent by the lambda environment: a ping with
+/// sequence number 0, followed by an invocation with sequence number 1.
+#[cfg(test)]
+pub(crate) const PING_AND_INVOKE: &[u8] = &[
+    47, 255, 129, 3, 1, 1, 7, 82, 101, 113, 117, 101, 115, 116, 1, 255, 130, 0, 1, 2, 1, 13, 83,
+    101, 114, 118, 105, 99, 101, 77, 101, 116, 104, 111, 100, 1, 12, 0, 1, 3, 83, 101, 113, 1, 6,
+    0, 0, 0, 18, 255, 130, 1, 13, 70, 117, 110, 99, 116, 105, 111, 110, 46, 80, 105, 110, 103, 0,
+    23, 255, 131, 3, 1, 1, 11, 80, 105, 110, 103, 82, 101, 113, 117, 101, 115, 116, 1, 255, 132, 0,
+    0, 0, 3, 255, 132, 0, 22, 255, 130, 1, 15, 70, 117, 110, 99, 116, 105, 111, 110, 46, 73, 110,
+    118, 111, 107, 101, 1, 1, 0, 255, 173, 255, 133, 3, 1, 1, 13, 73, 110, 118, 111, 107, 101, 82,
+    101, 113, 117, 101, 115, 116, 1, 255, 134, 0, 1, 8, 1, 7, 80, 97, 121, 108, 111, 97, 100, 1,
+    10, 0, 1, 9, 82, 101, 113, 117, 101, 115, 116, 73, 100, 1, 12, 0, 1, 12, 88, 65, 109, 122, 110,
+    84, 114, 97, 99, 101, 73, 100, 1, 12, 0, 1, 8, 68, 101, 97, 100, 108, 105, 110, 101, 1, 255,
+    136, 0, 1, 18, 73, 110, 118, 111, 107, 101, 100, 70, 117, 110, 99, 116, 105, 111, 110, 65, 114,
+    110, 1, 12, 0, 1, 17, 67, 111, 103, 110, 105, 116, 111, 73, 100, 101, 110, 116, 105, 116, 121,
+    73, 100, 1, 12, 0, 1, 21, 67, 111, 103, 110, 105, 116, 111, 73, 100, 101, 110, 116, 105, 116,
+    121, 80, 111, 111, 108, 73, 100, 1, 12, 0, 1, 13, 67, 108, 105, 101, 110, 116, 67, 111, 110,
+    116, 101, 120, 116, 1, 10, 0, 0, 0, 59, 255, 135, 3, 1, 1, 23, 73, 110, 118, 111, 107, 101, 82,
+    101, 113, 117, 101, 115, 116, 95, 84, 105, 109, 101, 115, 116, 97, 109, 112, 1, 255, 136, 0, 1,
+    2, 1, 7, 83, 101, 99, 111, 110, 100, 115, 1, 4, 0, 1, 5, 78, 97, 110, 111, 115, 1, 4, 0, 0, 0,
+    255, 244, 255, 134, 1, 49, 123, 34, 107, 101, 121, 51, 34, 58, 34, 118, 97, 108, 117, 101, 51,
+    34, 44, 34, 107, 101, 121, 50, 34, 58, 34, 118, 97, 108, 117, 101, 50, 34, 44, 34, 107, 101,
+    121, 49, 34, 58, 34, 118, 97, 108, 117, 101, 49, 34, 125, 1, 36, 50, 101, 100, 56, 48, 101, 52,
+    101, 45, 54, 49, 57, 54, 45, 49, 49, 101, 56, 45, 56, 55, 54, 97, 45, 52, 102, 52, 49, 98, 100,
+    56, 57, 51, 99, 52, 50, 1, 74, 82, 111, 111, 116, 61, 49, 45, 53, 98, 48, 97, 56, 52, 49, 53,
+    45, 49, 102, 98, 99, 49, 52, 50, 55, 98, 98, 56, 54, 56, 50, 53, 49, 54, 51, 48, 50, 97, 53,
+    53, 101, 59, 80, 97, 114, 101, 110, 116, 61, 49, 48, 101, 97, 99, 54, 101, 99, 52, 50, 50, 48,
+    54, 99, 53, 48, 59, 83, 97, 109, 112, 108, 101, 100, 61, 48, 1, 1, 252, 182, 21, 8, 50, 1, 252,
+    3, 234, 124, 228, 0, 1, 60, 97, 114, 110, 58, 97, 119, 115, 58, 108, 97, 109, 98, 100, 97, 58,
+    97, 112, 45, 115, 111, 117, 116, 104, 101, 97, 115, 116, 45, 50, 58, 55, 55, 49, 51, 49, 54,
+    48, 52, 51, 48, 51, 57, 58, 102, 117, 110, 99, 116, 105, 111, 110, 58, 116, 101, 115, 116, 70,
+    110, 71, 111, 0,
+];
+
+/// Another invocation with the given sequence number, to be appended to
+/// `PING_AND_INVOKE`. This reuses the type definitions and the invocation
+/// message of the first invocation, so it has the same request id and payload.
+#[cfg(test)]
+pub(crate) fn invoke_request(seq: u8) -> Vec<u8> {
+    assert!(seq < 128, "sequence numbers above 127 need more than one byte");
+    let mut bytes = vec![22, 255, 130, 1, 15];
+    bytes.extend_from_slice(b"Function.Invoke");
+    bytes.extend_from_slice(&[1, seq, 0]);
+    bytes.extend_from_slice(&PING_AND_INVOKE[353..]);
+    bytes
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -159,44 +217,11 @@ mod tests {
     use futures::Stream;
     use partial_io::{GenWouldBlock, PartialAsyncRead, PartialWithErrors};
 
-    use super::{Decoder, Request};
+    use super::{invoke_request, Decoder, Request, PING_AND_INVOKE};
 
     quickcheck! {
         fn decode_messages(seq: PartialWithErrors<GenWouldBlock>) -> bool {
-            let bytes = vec![
-                47, 255, 129, 3, 1, 1, 7, 82, 101, 113, 117, 101, 115, 116, 1, 255, 130, 0, 1, 2, 1,
-                13, 83, 101, 114, 118, 105, 99, 101, 77, 101, 116, 104, 111, 100, 1, 12, 0, 1, 3, 83,
-                101, 113, 1, 6, 0, 0, 0, 18, 255, 130, 1, 13, 70, 117, 110, 99, 116, 105, 111, 110, 46,
-                80, 105, 110, 103, 0, 23, 255, 131, 3, 1, 1, 11, 80, 105, 110, 103, 82, 101, 113, 117,
-                101, 115, 116, 1, 255, 132, 0, 0, 0, 3, 255, 132, 0, 22, 255, 130, 1, 15, 70, 117, 110,
-                99, 116, 105, 111, 110, 46, 73, 110, 118, 111, 107, 101, 1, 1, 0, 255, 173, 255, 133,
-                3, 1, 1, 13, 73, 110, 118, 111, 107, 101, 82, 101, 113, 117, 101, 115, 116, 1, 255,
-                134, 0, 1, 8, 1, 7, 80, 97, 121, 108, 111, 97, 100, 1, 10, 0, 1, 9, 82, 101, 113, 117,
-                101, 115, 116, 73, 100, 1, 12, 0, 1, 12, 88, 65, 109, 122, 110, 84, 114, 97, 99, 101,
-                73, 100, 1, 12, 0, 1, 8, 68, 101, 97, 100, 108, 105, 110, 101, 1, 255, 136, 0, 1, 18,
-                73, 110, 118, 111, 107, 101, 100, 70, 117, 110, 99, 116, 105, 111, 110, 65, 114, 110,
-                1, 12, 0, 1, 17, 67, 111, 103, 110, 105, 116, 111, 73, 100, 101, 110, 116, 105, 116,
-                121, 73, 100, 1, 12, 0, 1, 21, 67, 111, 103, 110, 105, 116, 111, 73, 100, 101, 110,
-                116, 105, 116, 121, 80, 111, 111, 108, 73, 100, 1, 12, 0, 1, 13, 67, 108, 105, 101,
-                110, 116, 67, 111, 110, 116, 101, 120, 116, 1, 10, 0, 0, 0, 59, 255, 135, 3, 1, 1, 23,
-                73, 110, 118, 111, 107, 101, 82, 101, 113, 117, 101, 115, 116, 95, 84, 105, 109, 101,
-                115, 116, 97, 109, 112, 1, 255, 136, 0, 1, 2, 1, 7, 83, 101, 99, 111, 110, 100, 115, 1,
-                4, 0, 1, 5, 78, 97, 110, 111, 115, 1, 4, 0, 0, 0, 255, 244, 255, 134, 1, 49, 123, 34,
-                107, 101, 121, 51, 34, 58, 34, 118, 97, 108, 117, 101, 51, 34, 44, 34, 107, 101, 121,
-                50, 34, 58, 34, 118, 97, 108, 117, 101, 50, 34, 44, 34, 107, 101, 121, 49, 34, 58, 34,
-                118, 97, 108, 117, 101, 49, 34, 125, 1, 36, 50, 101, 100, 56, 48, 101, 52, 101, 45, 54,
-                49, 57, 54, 45, 49, 49, 101, 56, 45, 56, 55, 54, 97, 45, 52, 102, 52, 49, 98, 100, 56,
-                57, 51, 99, 52, 50, 1, 74, 82, 111, 111, 116, 61, 49, 45, 53, 98, 48, 97, 56, 52, 49,
-                53, 45, 49, 102, 98, 99, 49, 52, 50, 55, 98, 98, 56, 54, 56, 50, 53, 49, 54, 51, 48,
-                50, 97, 53, 53, 101, 59, 80, 97, 114, 101, 110, 116, 61, 49, 48, 101, 97, 99, 54, 101,
-                99, 52, 50, 50, 48, 54, 99, 53, 48, 59, 83, 97, 109, 112, 108, 101, 100, 61, 48, 1, 1,
-                252, 182, 21, 8, 50, 1, 252, 3, 234, 124, 228, 0, 1, 60, 97, 114, 110, 58, 97, 119,
-                115, 58, 108, 97, 109, 98, 100, 97, 58, 97, 112, 45, 115, 111, 117, 116, 104, 101, 97,
-                115, 116, 45, 50, 58, 55, 55, 49, 51, 49, 54, 48, 52, 51, 48, 51, 57, 58, 102, 117,
-                110, 99, 116, 105, 111, 110, 58, 116, 101, 115, 116, 70, 110, 71, 111, 0,
-            ];
-
-            let pread = PartialAsyncRead::new(::std::io::Cursor::new(bytes), seq);
+            let pread = PartialAsyncRead::new(::std::io::Cursor::new(PING_AND_INVOKE), seq);
             let mut decoder =
                 Decoder::<_, HashMap<String, String>>::new(pread).wait();
 
@@ -226,4 +251,23 @@ mod tests {
             decoder.next().is_none()
         }
     }
+    #[test]
+    fn decode_appended_invoke() {
+        let mut bytes = PING_AND_INVOKE.to_vec();
+        bytes.extend(invoke_request(2));
+        let decoder = Decoder::<_, HashMap<String, String>>::new(::std::io::Cursor::new(bytes));
+
+        let seqs = decoder
+            .wait()
+            .map(|request| match request.unwrap() {
+                Request::Ping(seq) => seq,
+                Request::Invoke(seq, _, ctx, payload) => {
+                    assert_eq!("2ed80e4e-6196-11e8-876a-4f41bd893c42", ctx.aws_request_id());
+                    assert_eq!("value1", payload["key1"]);
+                    seq
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, vec![0, 1, 2]);
+    }
 }
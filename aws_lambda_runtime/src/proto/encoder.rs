@@ -12,6 +12,7 @@ use serde_schema::SchemaSerialize;
 use tokio::io::AsyncWrite;
 
 use super::super::error::ConnectionError;
+use super::super::invoke_error::IntoInvokeError;
 use super::messages;
 
 #[derive(Serialize, SchemaSerialize)]
@@ -26,12 +27,15 @@ struct RpcResponse<'a> {
     error: Option<String>,
 }
 
-pub enum Response<T> {
+pub enum Response<T, E> {
     Ping(u64),
-    Invoke(u64, Result<T, Error>),
+    Invoke(u64, Result<T, E>),
+    /// The invocation failed within the runtime, e.g. because the request
+    /// could not be decoded, or the handler exceeded its deadline.
+    Failed(u64, Error),
 }
 
-pub(crate) struct Encoder<W, T>
+pub(crate) struct Encoder<W, T, E>
 where
     W: AsyncWrite,
 {
@@ -39,17 +43,17 @@ where
     payload_buf: Vec<u8>,
     error_encoder: InvokeResponseErrorEncoder,
     stream: StreamSerializer<OutputBuffer>,
-    _phan: PhantomData<T>,
+    _phan: PhantomData<(T, E)>,
     type_id_response: TypeId,
     type_id_ping_response: TypeId,
     type_id_invoke_response: TypeId,
 }
 
-impl<W, T> Encoder<W, T>
+impl<W, T, E> Encoder<W, T, E>
 where
     W: AsyncWrite,
 {
-    pub fn new(w: W) -> Encoder<W, T> {
+    pub fn new(w: W) -> Encoder<W, T, E> {
         let payload_buf = Vec::with_capacity(4096);
         let error_encoder = InvokeResponseErrorEncoder::default();
 
@@ -72,6 +76,12 @@ where
         }
     }
 
+    /// Ask the lambda environment to terminate the process after
+    /// any subsequent error response.
+    pub fn set_should_exit(&mut self) {
+        self.error_encoder.should_exit = true;
+    }
+
     fn encode_ping(&mut self, seq: u64) -> Result<(), ConnectionError> {
         self.stream.serialize_with_type_id(
             self.type_id_response,
@@ -86,7 +96,11 @@ where
         Ok(())
     }
 
-    fn encode_invoke(&mut self, seq: u64, result: Result<T, Error>) -> Result<(), ConnectionError>
+    fn encode_invoke(
+        &mut self,
+        seq: u64,
+        result: Result<&T, &IntoInvokeError>,
+    ) -> Result<(), ConnectionError>
     where
         T: Serialize,
     {
@@ -101,7 +115,7 @@ where
         match result {
             Ok(payload) => {
                 self.payload_buf.clear();
-                match ::serde_json::to_writer(&mut self.payload_buf, &payload) {
+                match ::serde_json::to_writer(&mut self.payload_buf, payload) {
                     Ok(()) => {
                         self.stream.serialize_with_type_id(
                             self.type_id_invoke_response,
@@ -121,7 +135,7 @@ where
                 }
             }
             Err(err) => {
-                let invoke_error = self.error_encoder.encode(&err);
+                let invoke_error = self.error_encoder.encode(err);
                 self.stream.serialize_with_type_id(
                     self.type_id_invoke_response,
                     &messages::InvokeResponse::Error(invoke_error),
@@ -132,18 +146,23 @@ where
     }
 }
 
-impl<W, T> Sink for Encoder<W, T>
+impl<W, T, E> Sink for Encoder<W, T, E>
 where
     W: AsyncWrite,
     T: Serialize,
+    E: IntoInvokeError,
 {
-    type SinkItem = Response<T>;
+    type SinkItem = Response<T, E>;
     type SinkError = ConnectionError;
 
-    fn start_send(&mut self, res: Response<T>) -> StartSend<Self::SinkItem, Self::SinkError> {
+    fn start_send(&mut self, res: Response<T, E>) -> StartSend<Self::SinkItem, Self::SinkError> {
         match res {
             Response::Ping(seq) => self.encode_ping(seq)?,
-            Response::Invoke(seq, result) => self.encode_invoke(seq, result)?,
+            Response::Invoke(seq, result) => {
+                let result = result.as_ref().map_err(|err| err as &IntoInvokeError);
+                self.encode_invoke(seq, result)?
+            }
+            Response::Failed(seq, err) => self.encode_invoke(seq, Err(&err))?,
         }
         Ok(AsyncSink::Ready)
     }
@@ -161,29 +180,39 @@ where
 #[derive(Default)]
 struct InvokeResponseErrorEncoder {
     message_buf: String,
+    type_buf: String,
     backtrace_buf: String,
+    should_exit: bool,
 }
 
 impl InvokeResponseErrorEncoder {
-    fn encode<'a>(&'a mut self, err: &Error) -> messages::InvokeResponseError<'a> {
+    fn encode<'a>(&'a mut self, err: &IntoInvokeError) -> messages::InvokeResponseError<'a> {
         // Attempt to extract a stack trace from the error,
         // by rendering the opaque backtrace into a buffer,
         // and then running a parser over it.
         //
-        // If the parser fails, no stack trace will be included.
+        // If the error has no backtrace, or the parser fails,
+        // no stack trace will be included.
         self.backtrace_buf.clear();
-        write!(self.backtrace_buf, "{}", err.backtrace()).unwrap();
-        let stack_trace = Backtrace::parse(&self.backtrace_buf)
-            .map(messages::InvokeResponseErrorStackTrace)
-            .ok();
+        let stack_trace = match err.backtrace() {
+            Some(backtrace) => {
+                write!(self.backtrace_buf, "{}", backtrace).unwrap();
+                Backtrace::parse(&self.backtrace_buf)
+                    .map(messages::InvokeResponseErrorStackTrace)
+                    .ok()
+            }
+            None => None,
+        };
         // Render the rest of the error.
         self.message_buf.clear();
         write!(self.message_buf, "{}", err).unwrap();
+        self.type_buf.clear();
+        self.type_buf.push_str(err.error_type());
         messages::InvokeResponseError {
             message: &self.message_buf,
-            type_: "Error",
+            type_: &self.type_buf,
             stack_trace,
-            should_exit: false,
+            should_exit: self.should_exit,
         }
     }
 
@@ -197,8 +226,45 @@ impl InvokeResponseErrorEncoder {
             message: &self.message_buf,
             type_: "Error",
             stack_trace: None,
-            should_exit: false,
+            should_exit: self.should_exit,
+        }
+    }
+}
+
+/// Response as decoded from the output of an `Encoder`.
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+pub(crate) enum DecodedResponse {
+    Ping(u64),
+    Payload(u64, String),
+    /// The message of the error, and whether the process should exit.
+    Error(u64, String, bool),
+}
+
+/// Decode all responses from the output of an `Encoder`.
+#[cfg(test)]
+pub(crate) fn decode_responses(bytes: Vec<u8>) -> Vec<DecodedResponse> {
+    let mut de = ::gob::StreamDeserializer::new(::std::io::Cursor::new(bytes));
+    let mut responses = Vec::new();
+    loop {
+        let (service_method, seq) = match de.deserialize::<RpcResponse>().unwrap() {
+            Some(header) => (header.service_method.to_owned(), header.seq),
+            None => return responses,
+        };
+        if service_method == messages::SERVICE_METHOD_PING {
+            de.deserialize::<messages::PingResponse>().unwrap().unwrap();
+            responses.push(DecodedResponse::Ping(seq));
+            continue;
         }
+        let body = de.deserialize::<messages::InvokeResponse>().unwrap();
+        responses.push(match body.unwrap() {
+            messages::InvokeResponse::Payload(payload) => {
+                DecodedResponse::Payload(seq, String::from_utf8(payload.to_vec()).unwrap())
+            }
+            messages::InvokeResponse::Error(err) => {
+                DecodedResponse::Error(seq, err.message.to_owned(), err.should_exit)
+            }
+        });
     }
 }
 
@@ -221,7 +287,7 @@ mod tests {
 
         let mut buffer = ::std::io::Cursor::new(Vec::<u8>::new());
         {
-            let mut encoder = Encoder::<_, FailToSerialize>::new(&mut buffer).wait();
+            let mut encoder = Encoder::<_, FailToSerialize, Error>::new(&mut buffer).wait();
             encoder
                 .send(Response::Invoke(1, Ok(FailToSerialize)))
                 .unwrap();
@@ -255,12 +321,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn custom_error_type() {
+        #[derive(Debug)]
+        struct NotFound;
+        impl ::std::fmt::Display for NotFound {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, "resource was not found")
+            }
+        }
+        impl IntoInvokeError for NotFound {
+            fn error_type(&self) -> &str {
+                "NotFound"
+            }
+        }
+
+        let mut buffer = ::std::io::Cursor::new(Vec::<u8>::new());
+        {
+            let mut encoder = Encoder::<_, (), NotFound>::new(&mut buffer).wait();
+            encoder
+                .send(Response::Invoke(1, Err(NotFound)))
+                .unwrap();
+            encoder.flush().unwrap();
+        };
+        buffer.set_position(0);
+
+        let mut de = StreamDeserializer::new(buffer);
+
+        {
+            let header = de.deserialize::<RpcResponse>().unwrap().unwrap();
+
+            assert_eq!(header.service_method, "Function.Invoke");
+            assert_eq!(header.seq, 1);
+            assert_eq!(header.error, None);
+        }
+
+        {
+            let body = de
+                .deserialize::<messages::InvokeResponse>()
+                .unwrap()
+                .unwrap();
+
+            if let messages::InvokeResponse::Error(err) = body {
+                assert_eq!(err.type_, "NotFound");
+                assert_eq!(err.message, "resource was not found");
+                assert_eq!(err.should_exit, false);
+            } else {
+                panic!("not an invoke error")
+            }
+        }
+    }
+
     quickcheck! {
         fn encode_messages(seq: PartialWithErrors<GenWouldBlock>) -> bool {
             let mut write = ::std::io::Cursor::new(Vec::<u8>::new());
             {
                 let pwrite = PartialAsyncWrite::new(&mut write, seq);
-                let mut encoder = Encoder::<_, String>::new(pwrite).wait();
+                let mut encoder = Encoder::<_, String, Error>::new(pwrite).wait();
                 encoder.send(Response::Ping(0)).unwrap();
                 encoder.flush().unwrap();
                 encoder
@@ -10,6 +10,7 @@ pub(crate) const SERVICE_METHOD_INVOKE: &str = "Function.Invoke";
 pub(crate) struct PingRequest {}
 
 #[derive(Debug, Serialize, SchemaSerialize)]
+#[cfg_attr(test, derive(Deserialize))]
 pub(crate) struct PingResponse {}
 
 #[derive(Debug, Deserialize)]
@@ -5,3 +5,8 @@ mod payload;
 
 pub(crate) use self::decoder::{DecodeError, Decoder, Request};
 pub(crate) use self::encoder::{Encoder, Response};
+
+#[cfg(test)]
+pub(crate) use self::decoder::{invoke_request, PING_AND_INVOKE};
+#[cfg(test)]
+pub(crate) use self::encoder::{decode_responses, DecodedResponse};
@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Policy that decides when the process should be replaced by a fresh one.
+///
+/// Clones of a policy share their counters, so that all connections
+/// of a server count towards the same limits.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Policy {
+    pub(crate) max_invocations: Option<usize>,
+    pub(crate) max_memory_ratio: Option<f64>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    invocations: AtomicUsize,
+    pending: AtomicBool,
+}
+
+impl Policy {
+    /// Start counting from zero, for a new server.
+    pub(crate) fn reset(&mut self) {
+        self.counters = Arc::default();
+    }
+
+    /// Whether the process is due to be recycled.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.counters.pending.load(Ordering::SeqCst)
+    }
+
+    /// Record a completed invocation, and check whether the process
+    /// should be recycled afterwards.
+    ///
+    /// The memory limit of the function has been looked up when the runtime
+    /// was built, if `max_memory_ratio` is set (see `Config::resolve_memory_limit`).
+    pub(crate) fn record_invocation(&self, memory_limit: Option<usize>) -> bool {
+        let invocations = self.counters.invocations.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.should_recycle(invocations, memory_limit, resident_set_size) {
+            self.counters.pending.store(true, Ordering::SeqCst);
+        }
+        self.is_pending()
+    }
+
+    fn should_recycle<F>(&self, invocations: usize, memory_limit: Option<usize>, rss: F) -> bool
+    where
+        F: FnOnce() -> Option<usize>,
+    {
+        if let Some(max) = self.max_invocations {
+            if invocations >= max {
+                info!("recycling the process after {} invocations", invocations);
+                return true;
+            }
+        }
+        if let (Some(ratio), Some(memory_limit)) = (self.max_memory_ratio, memory_limit) {
+            let limit = memory_limit as f64 * ratio;
+            if let Some(rss) = rss() {
+                if rss as f64 >= limit {
+                    info!(
+                        "recycling the process at {} bytes of resident memory (limit: {:.0} bytes)",
+                        rss, limit
+                    );
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Returns the resident set size of the current process in bytes,
+/// if it can be determined on the current platform.
+fn resident_set_size() -> Option<usize> {
+    let mut status = String::new();
+    File::open("/proc/self/status")
+        .and_then(|mut file| file.read_to_string(&mut status))
+        .ok()?;
+    parse_vm_rss(&status)
+}
+
+fn parse_vm_rss(status: &str) -> Option<usize> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line["VmRSS:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_vm_rss, Policy};
+
+    #[test]
+    fn parses_vm_rss_from_status() {
+        let status = "Name:\tbootstrap\nVmPeak:\t  20000 kB\nVmRSS:\t   12345 kB\nThreads:\t2\n";
+        assert_eq!(parse_vm_rss(status), Some(12345 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tbootstrap\n"), None);
+    }
+
+    #[test]
+    fn recycles_after_max_invocations() {
+        let policy = Policy {
+            max_invocations: Some(3),
+            ..Policy::default()
+        };
+        assert!(!policy.should_recycle(2, None, || None));
+        assert!(policy.should_recycle(3, None, || None));
+    }
+
+    #[test]
+    fn counts_invocations_until_reset() {
+        let mut policy = Policy {
+            max_invocations: Some(2),
+            ..Policy::default()
+        };
+        assert!(!policy.record_invocation(None));
+        assert!(policy.clone().record_invocation(None));
+        assert!(policy.is_pending());
+
+        policy.reset();
+        assert!(!policy.is_pending());
+        assert!(!policy.record_invocation(None));
+    }
+
+    #[test]
+    fn default_policy_never_recycles() {
+        assert!(!Policy::default().should_recycle(1_000_000, Some(1 << 30), || Some(1 << 40)));
+    }
+
+    #[test]
+    fn recycles_above_memory_ratio() {
+        let policy = Policy {
+            max_memory_ratio: Some(0.5),
+            ..Policy::default()
+        };
+        let limit = Some(128 * 1024 * 1024);
+        assert!(!policy.should_recycle(1, limit, || Some(63 * 1024 * 1024)));
+        assert!(policy.should_recycle(1, limit, || Some(64 * 1024 * 1024)));
+        assert!(!policy.should_recycle(1, limit, || None));
+    }
+}
@@ -1,30 +1,44 @@
-use std::env;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use failure::Error;
-use futures::IntoFuture;
+use futures::{IntoFuture, Stream};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use tokio::net::TcpListener;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::reactor::Handle;
-use tokio::runtime::Runtime as TokioRuntime;
+use tokio::runtime::{Builder as TokioBuilder, Runtime as TokioRuntime};
 use tower_service::{NewService, Service};
 
+#[cfg(feature = "std-future")]
+use super::compat;
+use super::context::Context;
+use super::current_thread;
 use super::error::RuntimeError;
-use super::handler::Handler;
-use super::server::Server;
+use super::handler::{ContextHandler, Handler, StatefulHandler};
+use super::invoke_error::IntoInvokeError;
+use super::init;
+use super::server::{self, Config, Server};
 
 /// Runtime environment.
 #[derive(Debug)]
 pub struct Runtime {
     inner: TokioRuntime,
+    config: Config,
 }
 
 impl Runtime {
     /// Create a new `Runtime`, returning any error that happened during the creation.
+    ///
+    /// This uses the default settings, see `Runtime::builder` to customize them.
     pub fn new() -> Result<Runtime, RuntimeError> {
-        let inner = TokioRuntime::new().map_err(RuntimeError::from_io)?;
-        Ok(Runtime { inner })
+        RuntimeBuilder::new().build()
+    }
+
+    /// Create a `RuntimeBuilder` to configure a new `Runtime`.
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::new()
     }
 
     /// Retrieve a `Handle` to the underlying reactor.
@@ -36,37 +50,309 @@ impl Runtime {
     pub fn start<F, R, S>(self, f: F) -> Result<(), RuntimeError>
     where
         F: Fn(R) -> S + Send + Sync + 'static,
-        S: IntoFuture<Error = Error> + Send,
+        S: IntoFuture + Send,
         S::Future: Send,
+        S::Error: IntoInvokeError + Send + 'static,
         S::Item: Serialize + Send + 'static,
         R: DeserializeOwned + Send + 'static,
     {
         self.start_service(Handler::from(f))
     }
 
+    /// Start the runtime with the given async handler function.
+    ///
+    /// This is similar to `Runtime::start`, but accepts handler functions
+    /// that return a `std::future::Future`, such as `async` blocks.
+    /// The current `Context` remains available across `.await` points.
+    #[cfg(feature = "std-future")]
+    pub fn start_async<F, R, Fut, T, E>(self, f: F) -> Result<(), RuntimeError>
+    where
+        F: Fn(R) -> Fut + Send + Sync + 'static,
+        Fut: ::std::future::Future<Output = Result<T, E>> + Send + 'static,
+        T: Serialize + Send + 'static,
+        E: IntoInvokeError + Send + 'static,
+        R: DeserializeOwned + Send + 'static,
+    {
+        self.start(move |req| compat::into_01(f(req)))
+    }
+
+    /// Start the runtime with the given handler function, which receives
+    /// the invocation `Context` as an explicit argument.
+    pub fn start_with_context<F, R, S>(self, f: F) -> Result<(), RuntimeError>
+    where
+        F: Fn(R, Context) -> S + Send + Sync + 'static,
+        S: IntoFuture + Send,
+        S::Future: Send,
+        S::Error: IntoInvokeError + Send + 'static,
+        S::Item: Serialize + Send + 'static,
+        R: DeserializeOwned + Send + 'static,
+    {
+        self.start_service(ContextHandler::from(f))
+    }
+
+    /// Start the runtime with the given initialization future and handler function.
+    ///
+    /// The initialization future is run to completion once, before the runtime
+    /// starts to accept invocations. The resulting state is then passed by reference
+    /// into the handler function on every invocation.
+    ///
+    /// If the initialization fails, the runtime will not be started, and
+    /// the error will be returned.
+    pub fn start_with_init<I, T, F, R, S>(mut self, init: I, f: F) -> Result<(), RuntimeError>
+    where
+        I: IntoFuture<Item = T, Error = Error>,
+        I::Future: Send + 'static,
+        T: Send + Sync + 'static,
+        F: Fn(&T, R, Context) -> S + Send + Sync + 'static,
+        S: IntoFuture + Send,
+        S::Future: Send,
+        S::Error: IntoInvokeError + Send + 'static,
+        S::Item: Serialize + Send + 'static,
+        R: DeserializeOwned + Send + 'static,
+    {
+        let state = self
+            .inner
+            .block_on(init.into_future())
+            .map_err(RuntimeError::init)?;
+        self.start_service(StatefulHandler::new(state, f))
+    }
+
     /// Start the runtime with the given `Service`.
     pub fn start_service<S>(self, s: S) -> Result<(), RuntimeError>
     where
-        S: NewService<InitError = Error, Error = Error> + Send + 'static,
+        S: NewService<InitError = Error> + Send + 'static,
+        S::Error: IntoInvokeError + Send + 'static,
+        S::Service: Send + 'static,
+        <S::Service as Service>::Future: Send,
+        S::Future: Send + 'static,
+        S::Request: DeserializeOwned + Send + 'static,
+        S::Response: Serialize + Send + 'static,
+    {
+        let incoming = server::listen(&self.config)?;
+        self.serve(s, incoming)
+    }
+
+    /// Start the runtime with the given `Service`, accepting connections
+    /// from the given stream instead of listening on a TCP port.
+    ///
+    /// See `Server::new` for details.
+    pub fn serve<S, I>(self, s: S, incoming: I) -> Result<(), RuntimeError>
+    where
+        S: NewService<InitError = Error> + Send + 'static,
+        S::Error: IntoInvokeError + Send + 'static,
         S::Service: Send + 'static,
         <S::Service as Service>::Future: Send,
         S::Future: Send + 'static,
-        S::Request: DeserializeOwned + Send + Send + 'static,
+        S::Request: DeserializeOwned + Send + 'static,
         S::Response: Serialize + Send + 'static,
+        I: Stream<Error = io::Error> + Send + 'static,
+        I::Item: AsyncRead + AsyncWrite + Send + 'static,
     {
-        let port = server_port()?;
-        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
-        let listener = TcpListener::bind(&addr).map_err(RuntimeError::from_io)?;
-        let server = Server::new(s, listener.incoming());
+        let server = Server::with_config(s, incoming, self.config);
         self.inner.block_on_all(server)
     }
 }
 
-fn server_port() -> Result<u16, RuntimeError> {
-    let reason = "the _LAMBDA_SERVER_PORT variable must specify a valid port to listen on";
+/// Builds a `Runtime` with custom settings.
+///
+/// ## Example
+///
+/// ```no_run
+/// # extern crate aws_lambda_runtime;
+/// # extern crate failure;
+/// use std::time::Duration;
+/// use aws_lambda_runtime::Runtime;
+///
+/// # fn main() {
+/// let runtime = Runtime::builder()
+///     .worker_threads(1)
+///     .timeout_margin(Duration::from_millis(100))
+///     .catch_panics(true)
+///     .build()
+///     .unwrap();
+///
+/// runtime.start(|()| Ok::<_, failure::Error>("Hello ƛ!")).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct RuntimeBuilder {
+    worker_threads: Option<usize>,
+    config: Config,
+}
+
+impl RuntimeBuilder {
+    /// Create a new builder with the default settings.
+    pub fn new() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+
+    /// Set the number of worker threads that run the handler futures.
+    ///
+    /// Defaults to the number of cores available to the process.
+    /// This setting has no effect on a single-threaded runtime.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `threads` is zero.
+    pub fn worker_threads(mut self, threads: usize) -> RuntimeBuilder {
+        assert!(threads > 0, "the number of worker threads must be positive");
+        self.worker_threads = Some(threads);
+        self
+    }
+
+    /// Listen on the given address, instead of the port provided by the
+    /// lambda environment through the `_LAMBDA_SERVER_PORT` variable.
+    pub fn address(mut self, addr: SocketAddr) -> RuntimeBuilder {
+        self.config.addr = Some(addr);
+        self
+    }
+
+    /// Limit the number of invocations that are processed concurrently on a
+    /// single connection.
+    ///
+    /// Once the limit is reached, no further requests are read from the
+    /// connection until one of the pending invocations has completed.
+    /// By default, the number of in-flight invocations is not limited.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_in_flight(mut self, max: usize) -> RuntimeBuilder {
+        assert!(max > 0, "the in-flight limit must be positive");
+        self.config.max_in_flight = Some(max);
+        self
+    }
+
+    /// Enforce the invocation deadline, leaving the given margin.
+    ///
+    /// If the handler has not completed by the deadline minus the margin,
+    /// its future is dropped and the invocation fails with a timeout error.
+    /// This gives the runtime the chance to report the error before the
+    /// lambda environment terminates the invocation.
+    ///
+    /// By default, the deadline is not enforced by the runtime.
+    pub fn timeout_margin(mut self, margin: Duration) -> RuntimeBuilder {
+        self.config.timeout_margin = Some(margin);
+        self
+    }
+
+    /// Catch panics in the handler, and turn them into invocation errors.
+    ///
+    /// By default, a panicking handler tears down the connection it was
+    /// invoked on, causing the invocation to fail without an error message.
+    pub fn catch_panics(mut self, enabled: bool) -> RuntimeBuilder {
+        self.config.catch_panics = enabled;
+        self
+    }
+
+    /// Limit the time the runtime waits for after-response work.
+    ///
+    /// Work that has been registered through `Context::after_response` and
+    /// has not completed within the budget is dropped, and the runtime starts
+    /// accepting invocations again. By default, the runtime waits until all
+    /// of the work has completed.
+    pub fn after_response_budget(mut self, budget: Duration) -> RuntimeBuilder {
+        self.config.after_response_budget = Some(budget);
+        self
+    }
+
+    /// Recycle the process after the given number of invocations.
+    ///
+    /// Once the limit is reached, the runtime stops accepting invocations, and
+    /// `start` returns after the last response has been sent, so the process
+    /// exits and the lambda environment starts a fresh one for the next
+    /// invocation. If the last invocation failed, its error response asks the
+    /// environment to terminate the process right away. A successful response
+    /// can't do that, so the process is only replaced once it exits; make
+    /// sure that `main` returns soon after `start` does.
+    ///
+    /// This can be used to contain slow memory leaks, for instance in native
+    /// libraries that the handler depends on.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `invocations` is zero.
+    pub fn recycle_after_invocations(mut self, invocations: usize) -> RuntimeBuilder {
+        assert!(invocations > 0, "the number of invocations must be positive");
+        self.config.recycle.max_invocations = Some(invocations);
+        self
+    }
+
+    /// Recycle the process once its resident memory crosses the given ratio
+    /// of the function's memory limit (see `env::function_memory_size`).
+    ///
+    /// The memory usage is checked after every invocation, and the process
+    /// is recycled as described in `recycle_after_invocations`. The resident
+    /// memory can only be determined on Linux, so this has no effect on
+    /// other platforms.
+    ///
+    /// Building the runtime fails if the memory limit of the function
+    /// can't be determined (see `env::try_function_memory_size`).
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `ratio` is not within `(0, 1]`.
+    pub fn recycle_above_memory(mut self, ratio: f64) -> RuntimeBuilder {
+        assert!(
+            ratio > 0.0 && ratio <= 1.0,
+            "the memory ratio must be within (0, 1]"
+        );
+        self.config.recycle.max_memory_ratio = Some(ratio);
+        self
+    }
+
+    /// Log a warning when the heap usage of an invocation crosses the
+    /// given ratio of the function's memory limit (see `env::function_memory_size`).
+    ///
+    /// This requires the `memory::TrackingAllocator` to be installed as the
+    /// global allocator, and has no effect otherwise. Building the runtime
+    /// fails if the memory limit of the function can't be determined.
+    ///
+    /// The heap usage is tracked for the whole process, and the peak is reset
+    /// whenever an invocation starts. With more than one invocation in flight
+    /// at once, the usage of concurrent invocations is attributed to whichever
+    /// completes first, and warnings may be missed. Use `max_in_flight(1)` to
+    /// get accurate results when invocations overlap.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `ratio` is not within `(0, 1]`.
+    pub fn memory_warning(mut self, ratio: f64) -> RuntimeBuilder {
+        assert!(
+            ratio > 0.0 && ratio <= 1.0,
+            "the memory ratio must be within (0, 1]"
+        );
+        self.config.memory_warning = Some(ratio);
+        self
+    }
+
+    /// Log a summary line after every invocation.
+    ///
+    /// The summary contains the request id and duration of the invocation,
+    /// and for the first invocation of the process, the duration of the
+    /// initialization phase as well as the initialization type. If the
+    /// `memory::TrackingAllocator` is installed, the summary also contains
+    /// the peak heap usage of the invocation.
+    pub fn invocation_summary(mut self, enabled: bool) -> RuntimeBuilder {
+        self.config.invocation_summary = enabled;
+        self
+    }
+
+    /// Create the `Runtime`, returning any error that happened during the creation.
+    pub fn build(self) -> Result<Runtime, RuntimeError> {
+        init::mark_process_start();
+        let mut builder = TokioBuilder::new();
+        if let Some(threads) = self.worker_threads {
+            builder.core_threads(threads);
+        }
+        let config = self.config.resolve_memory_limit()?;
+        let inner = builder.build().map_err(RuntimeError::from_io)?;
+        Ok(Runtime { inner, config })
+    }
 
-    match env::var("_LAMBDA_SERVER_PORT") {
-        Ok(var) => var.parse().map_err(|_| RuntimeError::environment(reason)),
-        Err(_) => Err(RuntimeError::environment(reason)),
+    /// Create a single-threaded `current_thread::Runtime`, returning any
+    /// error that happened during the creation.
+    pub fn build_current_thread(self) -> Result<current_thread::Runtime, RuntimeError> {
+        current_thread::Runtime::with_config(self.config.resolve_memory_limit()?)
     }
 }
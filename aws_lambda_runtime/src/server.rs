@@ -1,113 +1,340 @@
+use std::env as std_env;
+use std::fmt;
 use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
 
-use failure::Error;
+use failure::{err_msg, Error};
+use futures::future::Executor;
 use futures::stream::FuturesUnordered;
+use futures::sync::oneshot;
 use futures::{Async, Future, Poll, Sink, Stream};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::executor::DefaultExecutor;
+use tokio::net::tcp::{Incoming, TcpListener};
+use tokio::timer::Delay;
 use tower_service::{NewService, Service};
 use void::Void;
 
-use super::context::Context;
+use super::context::{Cancellation, Context, Scoped, Work};
+use super::env;
 use super::error::{ConnectionError, RuntimeError};
+use super::init;
+use super::invoke_error::IntoInvokeError;
+use super::memory;
 use super::proto;
+use super::recycle;
 
-pub struct Server<S, I> {
+/// Settings that affect how the server handles invocations.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Config {
+    pub(crate) invocation_summary: bool,
+    pub(crate) addr: Option<SocketAddr>,
+    pub(crate) max_in_flight: Option<usize>,
+    pub(crate) timeout_margin: Option<Duration>,
+    pub(crate) catch_panics: bool,
+    pub(crate) after_response_budget: Option<Duration>,
+    pub(crate) recycle: recycle::Policy,
+    pub(crate) memory_warning: Option<f64>,
+    /// Memory limit of the function, if any of the settings depend on it.
+    pub(crate) memory_limit: Option<usize>,
+}
+
+impl Config {
+    /// Look up the memory limit of the function, if any of the settings depend on it.
+    ///
+    /// This is done once when the runtime is built, so a missing limit is
+    /// reported as an error instead of failing invocations later on.
+    pub(crate) fn resolve_memory_limit(mut self) -> Result<Config, RuntimeError> {
+        if self.recycle.max_memory_ratio.is_some() || self.memory_warning.is_some() {
+            let reason = "the AWS_LAMBDA_FUNCTION_MEMORY_SIZE variable must specify the memory limit of the function";
+            let limit = env::try_function_memory_size()
+                .ok_or_else(|| RuntimeError::environment(reason))?;
+            self.memory_limit = Some(limit);
+        }
+        Ok(self)
+    }
+}
+
+/// Server that accepts connections from the lambda environment,
+/// and dispatches invocations to a `NewService`.
+///
+/// The server is a `Future` that completes once it stops accepting connections,
+/// and all accepted connections have been closed. This makes it possible to
+/// spawn it onto an existing executor, instead of using a `Runtime`.
+/// Connections are spawned onto the default executor, so the server needs
+/// to be run within the context of a tokio runtime. Use `Server::executor`
+/// to spawn them onto a different executor.
+///
+/// ## Example
+///
+/// ```no_run
+/// # extern crate aws_lambda_runtime;
+/// # extern crate failure;
+/// # extern crate futures;
+/// # extern crate tokio;
+/// use aws_lambda_runtime::{Handler, Server};
+/// use futures::Future;
+///
+/// # fn main() {
+/// let handler = Handler::from(|()| Ok::<_, failure::Error>("Hello ƛ!"));
+/// let server = Server::bind(handler).unwrap();
+///
+/// tokio::run(server.map_err(|err| eprintln!("server error: {}", err)));
+/// # }
+/// ```
+pub struct Server<S, I, E = DefaultExecutor> {
     new_service: S,
     incoming: I,
+    incoming_done: bool,
+    connections: FuturesUnordered<oneshot::Receiver<()>>,
+    executor: E,
+    config: Config,
+}
+
+impl<S> Server<S, Incoming>
+where
+    S: NewService<InitError = Error>,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+{
+    /// Create a new `Server` that listens on the port provided by the lambda environment.
+    pub fn bind(new_service: S) -> Result<Server<S, Incoming>, RuntimeError> {
+        let config = Config::default();
+        let incoming = listen(&config)?;
+        Ok(Server::with_config(new_service, incoming, config))
+    }
 }
 
 impl<S, I> Server<S, I>
 where
-    S: NewService<Error = Error, InitError = Error> + 'static,
-    S::Future: Send + 'static,
-    S::Service: Send + 'static,
-    <S::Service as Service>::Future: Send,
-    S::Request: DeserializeOwned + Send + 'static,
-    S::Response: Serialize + Send + 'static,
-    I: Stream<Error = io::Error> + 'static,
-    I::Item: AsyncRead + AsyncWrite + Send + 'static,
+    S: NewService<InitError = Error>,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+    I: Stream<Error = io::Error>,
+    I::Item: AsyncRead + AsyncWrite,
 {
+    /// Create a new `Server` that accepts connections from the given stream.
+    ///
+    /// This makes it possible to use a transport other than the TCP listener
+    /// that is created by `Server::bind`, such as Unix domain sockets,
+    /// in-memory pipes or a socket that was opened by a supervisor process.
     pub fn new(new_service: S, incoming: I) -> Server<S, I> {
+        Server::with_config(new_service, incoming, Config::default())
+    }
+
+    pub(crate) fn with_config(new_service: S, incoming: I, mut config: Config) -> Server<S, I> {
+        init::mark_process_start();
+        config.recycle.reset();
         Server {
             new_service,
             incoming,
+            incoming_done: false,
+            connections: FuturesUnordered::new(),
+            executor: DefaultExecutor::current(),
+            config,
         }
     }
+}
 
-    fn spawn_service(&mut self) -> impl Future<Item = S::Service, Error = ()> {
-        self.new_service
-            .new_service()
-            .then(|service_result| match service_result {
-                Ok(service) => Ok(service),
-                Err(err) => {
-                    error!("service error: {}", err);
-                    Err(())
-                }
-            })
+impl<S, I, E> Server<S, I, E> {
+    /// Log a summary line after every invocation.
+    ///
+    /// See `RuntimeBuilder::invocation_summary` for details.
+    pub fn invocation_summary(mut self, enabled: bool) -> Server<S, I, E> {
+        self.config.invocation_summary = enabled;
+        self
     }
 
-    fn spawn(&mut self, stream: I::Item) -> Result<(), RuntimeError> {
-        let connection = self.spawn_service().and_then(|service| {
-            let connection = Connection::spawn(service, stream);
-            connection.then(|res| {
-                if let Err(err) = res {
-                    error!("connection error: {}", err);
-                }
-                Ok(())
-            })
-        });
-        ::tokio::spawn(connection);
+    /// Spawn connections onto the given executor, instead of the default executor.
+    ///
+    /// This makes it possible to serve handlers that are not `Send`, by spawning
+    /// the connections onto a `tokio::runtime::current_thread::TaskExecutor`.
+    pub fn executor<E2>(self, executor: E2) -> Server<S, I, E2> {
+        Server {
+            new_service: self.new_service,
+            incoming: self.incoming,
+            incoming_done: self.incoming_done,
+            connections: self.connections,
+            executor,
+            config: self.config,
+        }
+    }
+}
 
+impl<S, I, E> Server<S, I, E>
+where
+    S: NewService<InitError = Error>,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+    I: Stream<Error = io::Error>,
+    I::Item: AsyncRead + AsyncWrite,
+    E: Executor<ConnectionTask<S, I::Item>>,
+{
+    fn spawn(&mut self, io: I::Item) -> Result<(), RuntimeError> {
+        let (closed, on_closed) = oneshot::channel();
+        let task = ConnectionTask {
+            state: ConnectionState::Pending(
+                self.new_service.new_service(),
+                Some((io, self.config.clone())),
+            ),
+            _closed: closed,
+        };
+        self.executor.execute(task).map_err(|err| {
+            let reason = format!("failed to spawn a connection: {:?}", err.kind());
+            RuntimeError::from_io(io::Error::new(io::ErrorKind::Other, reason))
+        })?;
+        self.connections.push(on_closed);
         Ok(())
     }
 }
 
-impl<S, I> Future for Server<S, I>
+impl<S, I, E> fmt::Debug for Server<S, I, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("config", &self.config)
+            .field("connections", &self.connections.len())
+            .finish()
+    }
+}
+
+impl<S, I, E> Future for Server<S, I, E>
 where
-    S: NewService<InitError = Error, Error = Error> + 'static,
-    S::Service: Send + 'static,
-    <S::Service as Service>::Future: Send,
-    S::Future: Send + 'static,
-    S::Request: DeserializeOwned + Send + 'static,
-    S::Response: Serialize + Send + 'static,
-    I: Stream<Error = io::Error> + 'static,
-    I::Item: AsyncRead + AsyncWrite + Send + 'static,
+    S: NewService<InitError = Error>,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+    I: Stream<Error = io::Error>,
+    I::Item: AsyncRead + AsyncWrite,
+    E: Executor<ConnectionTask<S, I::Item>>,
 {
     type Item = ();
     type Error = RuntimeError;
 
     fn poll(&mut self) -> Poll<(), RuntimeError> {
+        // accept new connections first, as they create work for the connections,
+        // unless the process is due to be recycled
+        while !self.incoming_done && !self.config.recycle.is_pending() {
+            match self.incoming.poll().map_err(RuntimeError::from_io)? {
+                Async::Ready(Some(io)) => self.spawn(io)?,
+                Async::Ready(None) => self.incoming_done = true,
+                Async::NotReady => break,
+            }
+        }
+        // wait for the spawned connections next, until all of them are closed
         loop {
-            if let Some(stream) = try_ready!(self.incoming.poll().map_err(RuntimeError::from_io)) {
-                self.spawn(stream)?;
-            } else {
-                return Ok(Async::Ready(()));
+            match self.connections.poll() {
+                // the sender is dropped along with the connection
+                Ok(Async::Ready(Some(()))) | Err(oneshot::Canceled) => continue,
+                Ok(Async::Ready(None)) if self.incoming_done || self.config.recycle.is_pending() => {
+                    return Ok(Async::Ready(()));
+                }
+                Ok(_) => return Ok(Async::NotReady),
             }
         }
     }
 }
 
+/// A connection that has been accepted by a `Server`, and is spawned onto its executor.
+pub struct ConnectionTask<S, Io>
+where
+    S: NewService,
+    Io: AsyncRead + AsyncWrite,
+{
+    state: ConnectionState<S, Io>,
+    _closed: oneshot::Sender<()>,
+}
+
+impl<S, Io> fmt::Debug for ConnectionTask<S, Io>
+where
+    S: NewService,
+    Io: AsyncRead + AsyncWrite,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionTask").finish()
+    }
+}
+
+/// A connection that is either waiting for its service, or running.
+enum ConnectionState<S, Io>
+where
+    S: NewService,
+    Io: AsyncRead + AsyncWrite,
+{
+    Pending(S::Future, Option<(Io, Config)>),
+    Running(Box<Connection<S::Service, Io>>),
+}
+
+impl<S, Io> Future for ConnectionTask<S, Io>
+where
+    S: NewService<InitError = Error>,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+    Io: AsyncRead + AsyncWrite,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            let connection = match self.state {
+                ConnectionState::Pending(ref mut future, ref mut args) => match future.poll() {
+                    Ok(Async::Ready(service)) => {
+                        let (io, config) = args.take().expect("polled after completion");
+                        Connection::new(service, io, config)
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => {
+                        error!("service error: {}", err);
+                        return Ok(Async::Ready(()));
+                    }
+                },
+                ConnectionState::Running(ref mut connection) => match connection.poll() {
+                    Ok(ready) => return Ok(ready),
+                    Err(err) => {
+                        error!("connection error: {}", err);
+                        return Ok(Async::Ready(()));
+                    }
+                },
+            };
+            self.state = ConnectionState::Running(Box::new(connection));
+        }
+    }
+}
+
 struct Connection<S, Io>
 where
     S: Service,
-    Io: AsyncRead + AsyncWrite + Send + 'static,
+    Io: AsyncRead + AsyncWrite,
 {
     service: S,
     decoder: proto::Decoder<ReadHalf<Io>, S::Request>,
-    encoder: proto::Encoder<WriteHalf<Io>, S::Response>,
+    encoder: proto::Encoder<WriteHalf<Io>, S::Response, S::Error>,
     futures: FuturesUnordered<Invocation<S>>,
+    pending_work: Vec<Scoped<Work>>,
+    background: FuturesUnordered<Scoped<Work>>,
+    budget: Option<Delay>,
+    config: Config,
+    throttled: bool,
+    exiting: bool,
 }
 
 impl<S, Io> Connection<S, Io>
 where
-    S: Service<Error = Error> + 'static,
-    S::Request: DeserializeOwned + Send + 'static,
-    S::Response: Serialize + Send + 'static,
-    Io: AsyncRead + AsyncWrite + Send + 'static,
+    S: Service,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+    Io: AsyncRead + AsyncWrite,
 {
-    fn spawn(service: S, io: Io) -> Self {
+    fn new(service: S, io: Io, config: Config) -> Self {
         let (r, w) = io.split();
         let decoder = proto::Decoder::new(r);
         let encoder = proto::Encoder::new(w);
@@ -117,18 +344,85 @@ where
             decoder,
             encoder,
             futures: FuturesUnordered::new(),
+            pending_work: Vec::new(),
+            background: FuturesUnordered::new(),
+            budget: None,
+            config,
+            throttled: false,
+            exiting: false,
+        }
+    }
+
+    fn has_capacity(&self) -> bool {
+        if self.exiting || !self.pending_work.is_empty() || !self.background.is_empty() {
+            return false;
+        }
+        match self.config.max_in_flight {
+            Some(max) => self.futures.len() < max,
+            None => true,
         }
     }
 
+    fn start_background(&mut self) {
+        if self.pending_work.is_empty() {
+            return;
+        }
+        if self.background.is_empty() {
+            self.budget = self
+                .config
+                .after_response_budget
+                .map(|budget| Delay::new(Instant::now() + budget));
+        }
+        for work in self.pending_work.drain(..) {
+            self.background.push(work);
+        }
+    }
+
+    fn poll_background(&mut self) -> Async<()> {
+        loop {
+            match self.background.poll() {
+                Ok(Async::Ready(Some(()))) | Err(()) => continue,
+                Ok(Async::Ready(None)) => {
+                    self.budget = None;
+                    return Async::Ready(());
+                }
+                Ok(Async::NotReady) => break,
+            }
+        }
+        let expired = match self.budget {
+            Some(ref mut budget) => budget.poll().unwrap_or_else(|err| {
+                error!("timer error: {}", err);
+                Async::Ready(())
+            }),
+            None => Async::NotReady,
+        };
+        if expired.is_not_ready() {
+            return Async::NotReady;
+        }
+        warn!(
+            "dropping {} after-response futures that exceeded their budget",
+            self.background.len()
+        );
+        self.background = FuturesUnordered::new();
+        self.budget = None;
+        Async::Ready(())
+    }
+
     fn poll_encoder(&mut self) -> Poll<(), ConnectionError> {
         Ok(self.encoder.poll_complete()?)
     }
 
     fn poll_futures(&mut self) -> Poll<(), ConnectionError> {
         loop {
-            if let Some((seq, result)) = try_ready!(self.futures.poll()) {
-                self.encoder
-                    .start_send(proto::Response::Invoke(seq, result))?;
+            if let Some((response, work)) = try_ready!(self.futures.poll()) {
+                // stop accepting invocations once the process is due to be recycled,
+                // and ask the environment to terminate it when responding with an error
+                if self.config.recycle.record_invocation(self.config.memory_limit) {
+                    self.encoder.set_should_exit();
+                    self.exiting = true;
+                }
+                self.encoder.start_send(response)?;
+                self.pending_work.extend(work);
             } else {
                 return Ok(Async::Ready(()));
             }
@@ -137,6 +431,12 @@ where
 
     fn poll_decoder(&mut self) -> Poll<(), ConnectionError> {
         loop {
+            // stop reading requests while the in-flight limit is reached,
+            // or while after-response work is outstanding
+            self.throttled = !self.has_capacity();
+            if self.throttled {
+                return Ok(Async::NotReady);
+            }
             match self.decoder.poll() {
                 Ok(Async::Ready(Some(request))) => match request {
                     proto::Request::Ping(seq) => {
@@ -144,9 +444,38 @@ where
                         continue;
                     }
                     proto::Request::Invoke(seq, _deadline, ctx, payload) => {
-                        // TODO: enforce deadline
-                        let future = ctx.with(|| self.service.call(payload));
-                        self.futures.push(Invocation { seq, future, ctx });
+                        let started = Instant::now();
+                        memory::reset_peak();
+                        let service = &mut self.service;
+                        let call = catch_panic(self.config.catch_panics, || {
+                            ctx.with(|| service.call(payload))
+                        });
+                        match call {
+                            Ok(future) => {
+                                let timeout = self
+                                    .config
+                                    .timeout_margin
+                                    .map(|margin| ctx.cancellation(margin));
+                                self.futures.push(Invocation {
+                                    seq,
+                                    future,
+                                    ctx,
+                                    started,
+                                    timeout,
+                                    summary: self.config.invocation_summary,
+                                    memory_warning: self
+                                        .config
+                                        .memory_warning
+                                        .and_then(|ratio| {
+                                            self.config.memory_limit.map(|limit| (ratio, limit))
+                                        }),
+                                    catch_panics: self.config.catch_panics,
+                                });
+                            }
+                            Err(err) => {
+                                self.encoder.start_send(proto::Response::Failed(seq, err))?;
+                            }
+                        }
                         continue;
                     }
                 },
@@ -158,7 +487,7 @@ where
                 }
                 Err(proto::DecodeError::User(seq, err)) => {
                     self.encoder
-                        .start_send(proto::Response::Invoke(seq, Err(err)))?;
+                        .start_send(proto::Response::Failed(seq, err))?;
                     continue;
                 }
                 Err(proto::DecodeError::Frame(err)) => {
@@ -171,26 +500,40 @@ where
 
 impl<S, Io> Future for Connection<S, Io>
 where
-    S: Service<Error = Error> + 'static,
-    S::Request: DeserializeOwned + Send + 'static,
-    S::Response: Serialize + Send + 'static,
-    Io: AsyncRead + AsyncWrite + Send + 'static,
+    S: Service,
+    S::Error: IntoInvokeError,
+    S::Request: DeserializeOwned,
+    S::Response: Serialize,
+    Io: AsyncRead + AsyncWrite,
 {
     type Item = ();
     type Error = ConnectionError;
 
     fn poll(&mut self) -> Poll<(), ConnectionError> {
-        // poll the decoder first, as it may create work for futures and encoder
-        let decoder_ready = self.poll_decoder()?.is_ready();
-        // poll the futures next, as they might create work for the encoder
-        let futures_ready = self.poll_futures()?.is_ready();
-        // poll the encoder last, as it will never create other work
-        let encoder_ready = self.poll_encoder()?.is_ready();
-
-        if encoder_ready && futures_ready && decoder_ready {
-            Ok(Async::Ready(()))
-        } else {
-            Ok(Async::NotReady)
+        loop {
+            // poll the decoder first, as it may create work for futures and encoder
+            let decoder_ready = self.poll_decoder()?.is_ready();
+            // poll the futures next, as they might create work for the encoder
+            let futures_ready = self.poll_futures()?.is_ready();
+            // poll the encoder next, as it will never create other work
+            let encoder_ready = self.poll_encoder()?.is_ready();
+            // start after-response work only once the responses have been flushed
+            if encoder_ready {
+                self.start_background();
+            }
+            let background_ready = self.poll_background().is_ready();
+            // completed futures or work may have freed up capacity for the decoder
+            if self.throttled && self.has_capacity() {
+                continue;
+            }
+
+            // once exiting, the connection is done as soon as all work has completed
+            let decoder_ready = decoder_ready || self.exiting;
+            if encoder_ready && futures_ready && decoder_ready && background_ready {
+                return Ok(Async::Ready(()));
+            } else {
+                return Ok(Async::NotReady);
+            }
         }
     }
 }
@@ -199,22 +542,574 @@ struct Invocation<S: Service> {
     seq: u64,
     future: S::Future,
     ctx: Context,
+    started: Instant,
+    timeout: Option<Cancellation>,
+    summary: bool,
+    /// The ratio at which to warn, and the memory limit of the function.
+    memory_warning: Option<(f64, usize)>,
+    catch_panics: bool,
+}
+
+impl<S> Invocation<S>
+where
+    S: Service,
+{
+    fn poll_future(&mut self) -> Async<proto::Response<S::Response, S::Error>> {
+        let seq = self.seq;
+        let ctx = &self.ctx;
+        let future = &mut self.future;
+        match catch_panic(self.catch_panics, || ctx.with(|| future.poll())) {
+            Ok(Ok(Async::NotReady)) => Async::NotReady,
+            Ok(Ok(Async::Ready(res))) => Async::Ready(proto::Response::Invoke(seq, Ok(res))),
+            Ok(Err(err)) => Async::Ready(proto::Response::Invoke(seq, Err(err))),
+            Err(err) => Async::Ready(proto::Response::Failed(seq, err)),
+        }
+    }
+
+    fn poll_timeout(&mut self) -> Async<()> {
+        let result = match self.timeout {
+            Some(ref mut timeout) => timeout.poll(),
+            None => return Async::NotReady,
+        };
+        result.unwrap_or_else(|err| {
+            error!("timer error: {}", err);
+            self.timeout = None;
+            Async::NotReady
+        })
+    }
 }
 
 impl<S> Future for Invocation<S>
 where
     S: Service,
 {
-    type Item = (u64, Result<S::Response, S::Error>);
+    type Item = (proto::Response<S::Response, S::Error>, Vec<Scoped<Work>>);
     type Error = Void;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let seq = self.seq;
-        let future = &mut self.future;
-        self.ctx.with(|| match future.poll() {
-            Ok(Async::NotReady) => Ok(Async::NotReady),
-            Ok(Async::Ready(res)) => Ok(Async::Ready((seq, Ok(res)))),
-            Err(err) => Ok(Async::Ready((seq, Err(err)))),
-        })
+        let response = match self.poll_future() {
+            Async::Ready(response) => response,
+            Async::NotReady => match self.poll_timeout() {
+                Async::Ready(()) => proto::Response::Failed(
+                    self.seq,
+                    err_msg("invocation did not complete before its deadline"),
+                ),
+                Async::NotReady => return Ok(Async::NotReady),
+            },
+        };
+        let peak_memory = memory::peak_usage();
+        if self.summary {
+            log_summary(&self.ctx, self.started.elapsed(), peak_memory);
+        }
+        if let (Some((ratio, limit)), Some(peak_memory)) = (self.memory_warning, peak_memory) {
+            check_memory(&self.ctx, ratio, limit, peak_memory);
+        }
+        let ctx = &self.ctx;
+        let work = ctx
+            .take_after_response()
+            .into_iter()
+            .map(|work| ctx.scope(work))
+            .collect();
+        Ok(Async::Ready((response, work)))
+    }
+}
+
+/// Run the closure, turning a panic into an error if `enabled` is set.
+fn catch_panic<F, T>(enabled: bool, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> T,
+{
+    if !enabled {
+        return Ok(f());
+    }
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_owned()
+        };
+        err_msg(format!("handler panicked: {}", message))
+    })
+}
+
+/// Listen on the configured address, or the port provided by the lambda environment.
+pub(crate) fn listen(config: &Config) -> Result<Incoming, RuntimeError> {
+    let addr = match config.addr {
+        Some(addr) => addr,
+        None => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), server_port()?),
+    };
+    let listener = TcpListener::bind(&addr).map_err(RuntimeError::from_io)?;
+    Ok(listener.incoming())
+}
+
+fn server_port() -> Result<u16, RuntimeError> {
+    parse_server_port(std_env::var("_LAMBDA_SERVER_PORT"))
+}
+
+fn parse_server_port(var: Result<String, std_env::VarError>) -> Result<u16, RuntimeError> {
+    let reason = "the _LAMBDA_SERVER_PORT variable must specify a valid port to listen on";
+
+    match var {
+        Ok(var) => var.parse().map_err(|_| RuntimeError::environment(reason)),
+        Err(_) => Err(RuntimeError::environment(reason)),
+    }
+}
+
+fn log_summary(ctx: &Context, duration: Duration, peak_memory: Option<usize>) {
+    info!(
+        "{}",
+        format_summary(ctx, duration, peak_memory, env::initialization_type())
+    );
+}
+
+fn format_summary(
+    ctx: &Context,
+    duration: Duration,
+    peak_memory: Option<usize>,
+    init_type: env::InitializationType,
+) -> String {
+    let memory = match peak_memory {
+        Some(bytes) => format!(" Max Memory Used: {} MB", as_megabytes(bytes)),
+        None => String::new(),
+    };
+    if let Some(init_duration) = ctx.init_duration() {
+        format!(
+            "REPORT RequestId: {} Duration: {:.2} ms{} Cold Start: true Init Duration: {:.2} ms Init Type: {}",
+            ctx.aws_request_id(),
+            as_millis(duration),
+            memory,
+            as_millis(init_duration),
+            init_type
+        )
+    } else {
+        format!(
+            "REPORT RequestId: {} Duration: {:.2} ms{} Cold Start: false",
+            ctx.aws_request_id(),
+            as_millis(duration),
+            memory
+        )
+    }
+}
+
+fn check_memory(ctx: &Context, ratio: f64, limit: usize, peak_memory: usize) {
+    if peak_memory as f64 >= limit as f64 * ratio {
+        warn!(
+            "RequestId: {} Heap usage of {} MB exceeds {:.0}% of the memory limit of {} MB",
+            ctx.aws_request_id(),
+            as_megabytes(peak_memory),
+            ratio * 100.0,
+            as_megabytes(limit)
+        );
+    }
+}
+
+fn as_megabytes(bytes: usize) -> usize {
+    bytes / (1024 * 1024)
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e3 + f64::from(duration.subsec_nanos()) / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::io::{self, Read, Write};
+    use std::net::{TcpListener, TcpStream as StdTcpStream};
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use failure::Error;
+    use futures::{future, stream, task, Async, Future, Poll};
+    use tokio::io::{AsyncRead, AsyncWrite};
+    use tokio::net::TcpStream;
+
+    use super::{catch_panic, format_summary, listen, parse_server_port, Config, Server};
+    use context::Context;
+    use env::InitializationType;
+    use current_thread::{self, Runtime};
+    use handler::{Handler, StatefulHandler};
+    use proto::{self, decode_responses, DecodedResponse};
+    use runtime::RuntimeBuilder;
+
+    type UnitHandler = Handler<fn(()) -> Result<(), Error>, ()>;
+
+    fn handler() -> UnitHandler {
+        Handler::from(Ok as fn(()) -> Result<(), Error>)
+    }
+
+    /// In-memory transport that reads the given requests, and records the responses.
+    struct MemoryIo {
+        requests: io::Cursor<Vec<u8>>,
+        responses: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl MemoryIo {
+        fn new(requests: Vec<u8>) -> (MemoryIo, Arc<Mutex<Vec<u8>>>) {
+            let responses = Arc::new(Mutex::new(Vec::new()));
+            let io = MemoryIo {
+                requests: io::Cursor::new(requests),
+                responses: responses.clone(),
+            };
+            (io, responses)
+        }
+
+        fn incoming(self) -> stream::Once<MemoryIo, io::Error> {
+            stream::once(Ok(self))
+        }
+    }
+
+    impl Read for MemoryIo {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.requests.read(buf)
+        }
+    }
+
+    impl Write for MemoryIo {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.responses.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for MemoryIo {}
+
+    impl AsyncWrite for MemoryIo {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    /// A ping, followed by invocations with the sequence numbers 1 to `invocations`.
+    fn requests(invocations: u8) -> Vec<u8> {
+        let mut bytes = proto::PING_AND_INVOKE.to_vec();
+        for seq in 2..=invocations {
+            bytes.extend(proto::invoke_request(seq));
+        }
+        bytes
+    }
+
+    fn responses(output: &Arc<Mutex<Vec<u8>>>) -> Vec<DecodedResponse> {
+        decode_responses(output.lock().unwrap().clone())
+    }
+
+    #[test]
+    fn server_completes_when_incoming_ends() {
+        let incoming = stream::empty::<TcpStream, io::Error>();
+        Server::new(handler(), incoming).wait().unwrap();
+    }
+
+    #[test]
+    fn runtime_serves_custom_incoming() {
+        let incoming = stream::empty::<TcpStream, io::Error>();
+        Runtime::new().unwrap().serve(handler(), incoming).unwrap();
+    }
+
+    #[test]
+    fn runtime_answers_ping_and_invoke_over_custom_incoming() {
+        let handler =
+            Handler::from(|payload: HashMap<String, String>| Ok::<_, Error>(payload["key2"].clone()));
+
+        let (io, output) = MemoryIo::new(requests(1));
+        RuntimeBuilder::new()
+            .worker_threads(1)
+            .build()
+            .unwrap()
+            .serve(handler, io.incoming())
+            .unwrap();
+
+        assert_eq!(
+            responses(&output),
+            vec![
+                DecodedResponse::Ping(0),
+                DecodedResponse::Payload(1, r#""value2""#.to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stateful_handler_shares_state_initialized_once() {
+        let inits = Rc::new(Cell::new(0));
+        let mut runtime = Runtime::new().unwrap();
+        let state = {
+            let inits = inits.clone();
+            runtime
+                .init(future::lazy(move || {
+                    inits.set(inits.get() + 1);
+                    Ok::<_, Error>(Cell::new(0))
+                }))
+                .unwrap()
+        };
+        let handler = StatefulHandler::new(state, |invocations: &Cell<usize>, (), _| {
+            invocations.set(invocations.get() + 1);
+            Ok::<_, Error>(invocations.get())
+        });
+
+        // two connections with two invocations each
+        let (first, first_output) = MemoryIo::new(requests(2));
+        let (second, second_output) = MemoryIo::new(requests(2));
+        runtime
+            .serve(handler, stream::iter_ok(vec![first, second]))
+            .unwrap();
+
+        assert_eq!(inits.get(), 1);
+        let mut counts = responses(&first_output);
+        counts.extend(responses(&second_output));
+        counts.retain(|response| match *response {
+            DecodedResponse::Payload(..) => true,
+            _ => false,
+        });
+        assert_eq!(counts.len(), 4);
+        assert!(counts.contains(&DecodedResponse::Payload(2, "4".to_owned())));
+    }
+
+    #[test]
+    fn current_thread_runtime_serves_handler_that_is_not_send() {
+        let invocations = Rc::new(Cell::new(0));
+        let handler = {
+            let invocations = invocations.clone();
+            Handler::from(move |payload: HashMap<String, String>| {
+                invocations.set(invocations.get() + 1);
+                Ok::<_, Error>(payload["key1"].clone())
+            })
+        };
+
+        let (io, output) = MemoryIo::new(requests(2));
+        Runtime::new().unwrap().serve(handler, io.incoming()).unwrap();
+
+        assert_eq!(invocations.get(), 2);
+        assert_eq!(
+            responses(&output),
+            vec![
+                DecodedResponse::Ping(0),
+                DecodedResponse::Payload(1, r#""value1""#.to_owned()),
+                DecodedResponse::Payload(2, r#""value1""#.to_owned()),
+            ]
+        );
+    }
+
+    /// Handler future that completes on its second poll, and keeps track of
+    /// the maximum number of handler futures that were running at once.
+    struct YieldOnce {
+        running: Rc<Cell<usize>>,
+        max_running: Rc<Cell<usize>>,
+        polled: bool,
+    }
+
+    impl Future for YieldOnce {
+        type Item = ();
+        type Error = Error;
+
+        fn poll(&mut self) -> Poll<(), Error> {
+            if self.polled {
+                self.running.set(self.running.get() - 1);
+                return Ok(Async::Ready(()));
+            }
+            self.polled = true;
+            self.running.set(self.running.get() + 1);
+            self.max_running
+                .set(self.max_running.get().max(self.running.get()));
+            task::current().notify();
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn max_running_invocations(max_in_flight: Option<usize>) -> usize {
+        let running = Rc::new(Cell::new(0));
+        let max_running = Rc::new(Cell::new(0));
+        let handler = {
+            let max_running = max_running.clone();
+            Handler::from(move |()| YieldOnce {
+                running: running.clone(),
+                max_running: max_running.clone(),
+                polled: false,
+            })
+        };
+
+        let mut builder = RuntimeBuilder::new();
+        if let Some(max) = max_in_flight {
+            builder = builder.max_in_flight(max);
+        }
+        let (io, output) = MemoryIo::new(requests(3));
+        builder
+            .build_current_thread()
+            .unwrap()
+            .serve(handler, io.incoming())
+            .unwrap();
+
+        assert_eq!(responses(&output).len(), 4);
+        max_running.get()
+    }
+
+    #[test]
+    fn max_in_flight_limits_concurrent_invocations() {
+        assert_eq!(max_running_invocations(None), 3);
+        assert_eq!(max_running_invocations(Some(2)), 2);
+        assert_eq!(max_running_invocations(Some(1)), 1);
+    }
+
+    #[test]
+    fn timeout_margin_fails_invocation_at_deadline() {
+        // the deadline of the invocation lies in the past
+        let handler = Handler::from(|()| future::empty::<(), Error>());
+
+        let (io, output) = MemoryIo::new(requests(1));
+        RuntimeBuilder::new()
+            .timeout_margin(Duration::from_millis(0))
+            .build_current_thread()
+            .unwrap()
+            .serve(handler, io.incoming())
+            .unwrap();
+
+        assert_eq!(
+            responses(&output),
+            vec![
+                DecodedResponse::Ping(0),
+                DecodedResponse::Error(
+                    1,
+                    "invocation did not complete before its deadline".to_owned(),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn listen_binds_configured_address() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = Config {
+            addr: Some(addr),
+            ..Config::default()
+        };
+
+        let _incoming = listen(&config).unwrap();
+        StdTcpStream::connect(addr).unwrap();
+    }
+
+    #[test]
+    fn after_response_work_runs_between_response_and_next_invocation() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (io, output) = MemoryIo::new(requests(2));
+        let handler = {
+            let log = log.clone();
+            Handler::from(move |()| {
+                let sent = responses(&output).len();
+                log.borrow_mut().push(format!("invoke, {} responses sent", sent));
+                let (log, output) = (log.clone(), output.clone());
+                current_thread::after_response(future::lazy(move || {
+                    let sent = responses(&output).len();
+                    log.borrow_mut().push(format!("work, {} responses sent", sent));
+                    Ok(())
+                }));
+                Ok::<_, Error>(())
+            })
+        };
+
+        // the lambda environment only sends an invocation once the previous one has completed
+        RuntimeBuilder::new()
+            .max_in_flight(1)
+            .build_current_thread()
+            .unwrap()
+            .serve(handler, io.incoming())
+            .unwrap();
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "invoke, 0 responses sent",
+                "work, 2 responses sent",
+                "invoke, 2 responses sent",
+                "work, 3 responses sent",
+            ]
+        );
+    }
+
+    #[test]
+    fn after_response_budget_drops_work_that_does_not_complete() {
+        let handler = Handler::from(|()| {
+            Context::current().after_response(future::empty());
+            Ok::<_, Error>(())
+        });
+
+        let (io, output) = MemoryIo::new(requests(2));
+        RuntimeBuilder::new()
+            .after_response_budget(Duration::from_millis(10))
+            .build_current_thread()
+            .unwrap()
+            .serve(handler, io.incoming())
+            .unwrap();
+
+        assert_eq!(
+            responses(&output),
+            vec![
+                DecodedResponse::Ping(0),
+                DecodedResponse::Payload(1, "null".to_owned()),
+                DecodedResponse::Payload(2, "null".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn catch_panic_returns_value() {
+        assert_eq!(catch_panic(true, || 42).unwrap(), 42);
+    }
+
+    #[test]
+    fn catch_panic_turns_panic_into_error() {
+        let err = catch_panic(true, || -> () { panic!("oh no") }).unwrap_err();
+        assert_eq!(err.to_string(), "handler panicked: oh no");
+    }
+
+    #[test]
+    fn summary_of_warm_invocation() {
+        let ctx = Context::builder().aws_request_id("test-request").build();
+
+        assert_eq!(
+            format_summary(
+                &ctx,
+                Duration::from_micros(12_345),
+                None,
+                InitializationType::OnDemand
+            ),
+            "REPORT RequestId: test-request Duration: 12.35 ms Cold Start: false"
+        );
+    }
+
+    #[test]
+    fn summary_of_cold_invocation() {
+        let ctx = Context::builder()
+            .aws_request_id("test-request")
+            .init_duration(Duration::from_millis(250))
+            .build();
+
+        assert_eq!(
+            format_summary(
+                &ctx,
+                Duration::from_millis(3),
+                Some(42 * 1024 * 1024 + 1),
+                InitializationType::SnapStart
+            ),
+            "REPORT RequestId: test-request Duration: 3.00 ms Max Memory Used: 42 MB \
+             Cold Start: true Init Duration: 250.00 ms Init Type: snap-start"
+        );
+    }
+
+    #[test]
+    fn server_port_is_parsed_from_variable() {
+        assert_eq!(parse_server_port(Ok("9001".to_owned())).unwrap(), 9001);
+        assert!(parse_server_port(Ok("port".to_owned())).is_err());
+        assert!(parse_server_port(Ok("65536".to_owned())).is_err());
+        assert!(parse_server_port(Err(::std::env::VarError::NotPresent)).is_err());
     }
 }
@@ -1,5 +1,3 @@
-use std::io;
-
 use bytes::Buf;
 use futures::{Async, Poll};
 use void::Void;
@@ -15,15 +13,11 @@ impl BufStream for RequestBody {
     type Error = Void;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        Ok(Async::Ready(
-            self.0
-                .take()
-                .map(|body| RequestBodyBuf(io::Cursor::new(body))),
-        ))
+        Ok(Async::Ready(self.0.take().map(RequestBodyBuf)))
     }
 }
 
-pub struct RequestBodyBuf(io::Cursor<Body>);
+pub struct RequestBodyBuf(Body);
 
 impl Buf for RequestBodyBuf {
     fn remaining(&self) -> usize {